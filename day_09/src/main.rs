@@ -1,6 +1,6 @@
 use general::read_data_lines;
 use ndarray::{Array, Array2, ArrayView};
-use std::collections::BTreeSet;
+use std::collections::VecDeque;
 use structopt::StructOpt;
 
 const PUZZLE_NAME: &str = "Advent of Code: Day 9 -- Version:";
@@ -24,77 +24,151 @@ fn get_heatmap(data: &[String]) -> Array2<u32> {
     heatmap
 }
 
-fn get_lowpoints(heatmap: &Array2<u32>) -> Vec<(usize, usize)> {
-    let (nrow, ncol) = (heatmap.nrows(), heatmap.ncols());
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Connectivity {
+    Four,
+    Eight,
+}
+
+// the rules used to flood a heatmap: cells at or above `wall` are basin walls,
+// `strict` low points must be lower than every neighbour, otherwise lower or equal
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct FloodModel {
+    wall: u32,
+    connectivity: Connectivity,
+    strict: bool,
+}
+impl Default for FloodModel {
+    fn default() -> Self {
+        Self {
+            wall: 9,
+            connectivity: Connectivity::Four,
+            strict: true,
+        }
+    }
+}
 
+fn get_adjacents(heatmap: &Array2<u32>, position: (usize, usize), connectivity: Connectivity) -> Vec<(usize, usize)> {
+    let (r, c) = (position.0 as i64, position.1 as i64);
+    let offsets: &[(i64, i64)] = match connectivity {
+        Connectivity::Four => &[(-1, 0), (1, 0), (0, -1), (0, 1)],
+        Connectivity::Eight => &[(-1, 0), (1, 0), (0, -1), (0, 1), (-1, -1), (-1, 1), (1, -1), (1, 1)],
+    };
+    let (nrow, ncol) = (heatmap.nrows() as i64, heatmap.ncols() as i64);
+    offsets
+        .iter()
+        .map(|(i, j)| (r + i, c + j))
+        .filter(|(i, j)| (0..nrow).contains(i) && (0..ncol).contains(j))
+        .map(|(i, j)| (i as usize, j as usize))
+        .collect::<Vec<(_, _)>>()
+}
+
+fn get_lowpoints(heatmap: &Array2<u32>, model: &FloodModel) -> Vec<(usize, usize)> {
     let is_lowpoint = |r, c, n| {
-        (r == 0 || heatmap[[r - 1, c]] > n)
-            && (r + 1 == nrow || heatmap[[r + 1, c]] > n)
-            && (c == 0 || heatmap[[r, c - 1]] > n)
-            && (c + 1 == ncol || heatmap[[r, c + 1]] > n)
+        n < model.wall
+            && get_adjacents(heatmap, (r, c), model.connectivity)
+                .iter()
+                .all(|&(i, j)| match model.strict {
+                    true => heatmap[[i, j]] > n,
+                    false => heatmap[[i, j]] >= n,
+                })
     };
 
     let mut lowpoints = vec![];
-    for row in 0..nrow {
-        for col in 0..ncol {
-            if is_lowpoint(row, col, heatmap[[row, col]]) {
-                lowpoints.push((row, col));
-            }
+    for ((row, col), &n) in heatmap.indexed_iter() {
+        if is_lowpoint(row, col, n) {
+            lowpoints.push((row, col));
         }
     }
     lowpoints
 }
 
-fn find_basin(heatmap: &Array2<u32>, point: &(usize, usize), basin: &mut BTreeSet<(usize, usize)>) {
-    if basin.contains(point) {
-        return;
-    }
-    basin.insert(*point);
+#[derive(Debug, Clone, PartialEq)]
+struct Basin {
+    size: usize,
+    low_point: (usize, usize),
+    // rise from the low point to the highest cell in the basin
+    depth: u32,
+}
 
-    let (r, c) = *point;
-    let n = heatmap[[r, c]];
+// label every non-wall cell with the id of the basin it flows into, walls are labelled 0
+// basin ids start at 1, basins[id - 1] describes the basin with that id
+fn get_basins(heatmap: &Array2<u32>, model: &FloodModel) -> (Array2<u32>, Vec<Basin>) {
+    let mut labels = Array2::<u32>::zeros(heatmap.dim());
+    let mut basins = vec![];
+    let mut queue = VecDeque::new();
 
-    let mut adjacents = vec![];
-    if r != 0 && heatmap[[r - 1, c]] > n {
-        adjacents.push((r - 1, c));
-    }
-    if r + 1 < heatmap.nrows() && heatmap[[r + 1, c]] > n {
-        adjacents.push((r + 1, c));
+    for ((row, col), &height) in heatmap.indexed_iter() {
+        if height >= model.wall || labels[[row, col]] != 0 {
+            continue;
+        }
+
+        // breadth-first flood of an unlabelled cell, no recursion so large basins can't blow the stack
+        let id = basins.len() as u32 + 1;
+        let mut basin = Basin {
+            size: 0,
+            low_point: (row, col),
+            depth: 0,
+        };
+        let mut highest = height;
+
+        labels[[row, col]] = id;
+        queue.push_back((row, col));
+        while let Some((r, c)) = queue.pop_front() {
+            let n = heatmap[[r, c]];
+            basin.size += 1;
+            if n < heatmap[basin.low_point] {
+                basin.low_point = (r, c);
+            }
+            highest = highest.max(n);
+
+            for (i, j) in get_adjacents(heatmap, (r, c), model.connectivity) {
+                if heatmap[[i, j]] < model.wall && labels[[i, j]] == 0 {
+                    labels[[i, j]] = id;
+                    queue.push_back((i, j));
+                }
+            }
+        }
+        basin.depth = highest - heatmap[basin.low_point];
+        basins.push(basin);
     }
-    if c != 0 && heatmap[[r, c - 1]] > n {
-        adjacents.push((r, c - 1));
+    (labels, basins)
+}
+
+fn display_basins(labels: &Array2<u32>, basins: &[Basin]) {
+    for (i, basin) in basins.iter().enumerate() {
+        println!(
+            "basin {}: size = {}, low point = {:?}, depth = {}",
+            i + 1,
+            basin.size,
+            basin.low_point,
+            basin.depth
+        );
     }
-    if c + 1 < heatmap.ncols() && heatmap[[r, c + 1]] > n {
-        adjacents.push((r, c + 1));
+    for row in labels.rows() {
+        println!("{}", row.iter().map(|id| id.to_string()).collect::<Vec<_>>().join(" "));
     }
-
-    adjacents
-        .iter()
-        .filter(|(r, c)| heatmap[[*r, *c]] != 9)
-        .for_each(|p| find_basin(heatmap, p, basin));
 }
 
-fn get_solution1(heatmap: &Array2<u32>) -> u32 {
-    get_lowpoints(heatmap)
+fn get_solution1(heatmap: &Array2<u32>, model: &FloodModel) -> u32 {
+    get_lowpoints(heatmap, model)
         .iter()
         .map(|(r, c)| heatmap[[*r, *c]] + 1)
         .sum::<_>()
 }
 
-fn get_solution2(heatmap: &Array2<u32>) -> u32 {
-    let mut basin_sizes = vec![];
-
-    for point in get_lowpoints(heatmap) {
-        let mut basin = BTreeSet::<(usize, usize)>::new();
-        find_basin(heatmap, &point, &mut basin);
-        basin_sizes.push(basin.len());
-    }
+// product of the three largest basin sizes, or of all of them when a model merges basins into fewer than three
+fn get_solution2(heatmap: &Array2<u32>, model: &FloodModel) -> Result<u64, String> {
+    let (_, basins) = get_basins(heatmap, model);
+    let mut basin_sizes = basins.iter().map(|b| b.size).collect::<Vec<_>>();
 
-    assert!(basin_sizes.len() > 2);
     basin_sizes.sort_by(|a, b| b.cmp(a));
     //(basin_sizes[0] * basin_sizes[1] * basin_sizes[2]) as u32
-    //basin_sizes.iter().take(3).fold(1, |acc, x| acc * x) as u32
-    basin_sizes.iter().take(3).product::<usize>() as u32
+    basin_sizes
+        .iter()
+        .take(3)
+        .try_fold(1u64, |acc, &x| acc.checked_mul(x as u64))
+        .ok_or_else(|| "product of the basin sizes overflows u64".to_string())
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -108,6 +182,23 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             help = "file|stdin -- puzzle input"
         )]
         input: Option<std::path::PathBuf>,
+
+        #[structopt(short, long, help = "print the basin summary and label map")]
+        basins: bool,
+
+        #[structopt(
+            short,
+            long,
+            default_value = "9",
+            help = "height at or above which a cell is a basin wall"
+        )]
+        wall: u32,
+
+        #[structopt(short, long, help = "flood diagonally (8-connectivity) instead of 4-connectivity")]
+        diagonal: bool,
+
+        #[structopt(short, long, help = "allow low points to equal their neighbours")]
+        plateau: bool,
     }
     let args = Cli::from_args();
 
@@ -116,9 +207,22 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let data = read_data_lines::<String>(args.input)?;
     let heatmap = get_heatmap(&data);
     //println!("heatmap = {:?}", heatmap);
+    let model = FloodModel {
+        wall: args.wall,
+        connectivity: match args.diagonal {
+            true => Connectivity::Eight,
+            false => Connectivity::Four,
+        },
+        strict: !args.plateau,
+    };
 
-    println!("Answer Part 1 = {}", get_solution1(&heatmap));
-    println!("Answer Part 2 = {}", get_solution2(&heatmap));
+    if args.basins {
+        let (labels, basins) = get_basins(&heatmap, &model);
+        display_basins(&labels, &basins);
+    }
+
+    println!("Answer Part 1 = {}", get_solution1(&heatmap, &model));
+    println!("Answer Part 2 = {}", get_solution2(&heatmap, &model)?);
     Ok(())
 }
 
@@ -135,24 +239,88 @@ mod tests {
     #[test]
     fn part1_example() {
         let data = datapoints("input-example");
-        assert_eq!(get_solution1(&data), 15);
+        assert_eq!(get_solution1(&data, &FloodModel::default()), 15);
     }
 
     #[test]
     fn part1_actual() {
         let data = datapoints("input-actual");
-        assert_eq!(get_solution1(&data), 572);
+        assert_eq!(get_solution1(&data, &FloodModel::default()), 572);
     }
 
     #[test]
     fn part2_example() {
         let data = datapoints("input-example");
-        assert_eq!(get_solution2(&data), 1134);
+        assert_eq!(get_solution2(&data, &FloodModel::default()).unwrap(), 1134);
     }
 
     #[test]
     fn part2_actual() {
         let data = datapoints("input-actual");
-        assert_eq!(get_solution2(&data), 847044);
+        assert_eq!(get_solution2(&data, &FloodModel::default()).unwrap(), 847044);
+    }
+
+    #[test]
+    fn part2_example_diagonal() {
+        let data = datapoints("input-example");
+        let model = FloodModel {
+            connectivity: Connectivity::Eight,
+            ..FloodModel::default()
+        };
+        let (_, basins) = get_basins(&data, &model);
+        assert!(basins.len() < 3);
+        assert_eq!(get_solution2(&data, &model).unwrap(), 35);
+        assert_eq!(get_solution2(&data, &FloodModel { wall: 3, ..model }).unwrap(), 12);
+    }
+
+    #[test]
+    fn part2_large_basins() {
+        // three basins of 2000 cells, whose product no longer fits in a u32
+        let basin = format!("0{}", "1".repeat(1999));
+        let data = get_heatmap(&[[basin.as_str(); 3].join("9")]);
+        assert_eq!(get_solution2(&data, &FloodModel::default()).unwrap(), 8_000_000_000);
+    }
+
+    #[test]
+    fn basin_map_example() {
+        let data = datapoints("input-example");
+        let (labels, basins) = get_basins(&data, &FloodModel::default());
+        assert_eq!(basins.len(), 4);
+        assert_eq!(basins.iter().map(|b| b.size).collect::<Vec<_>>(), vec![3, 9, 14, 9]);
+        assert_eq!(basins[0].low_point, (0, 1));
+        assert_eq!(basins[0].depth, 2);
+        assert_eq!(labels[[0, 0]], labels[[1, 0]]);
+        assert_eq!(labels[[0, 2]], 0);
+    }
+
+    #[test]
+    fn basin_map_plateau() {
+        let data = get_heatmap(&["5595".to_string(), "5595".to_string()]);
+        let (labels, basins) = get_basins(&data, &FloodModel::default());
+        assert_eq!(basins.len(), 2);
+        assert_eq!(basins[0].size, 4);
+        assert_eq!(basins[0].depth, 0);
+        assert_eq!(labels[[1, 3]], 2);
+    }
+
+    #[test]
+    fn flood_models() {
+        let data = get_heatmap(&["5595".to_string(), "5595".to_string()]);
+        let model = FloodModel::default();
+        assert!(get_lowpoints(&data, &model).is_empty());
+
+        let plateau = FloodModel { strict: false, ..model };
+        assert_eq!(get_lowpoints(&data, &plateau).len(), 6);
+
+        let low_walls = FloodModel { wall: 5, ..model };
+        assert!(get_basins(&data, &low_walls).1.is_empty());
+
+        let data = get_heatmap(&["1921".to_string(), "9199".to_string()]);
+        assert_eq!(get_basins(&data, &model).1.len(), 3);
+        let diagonal = FloodModel {
+            connectivity: Connectivity::Eight,
+            ..model
+        };
+        assert_eq!(get_basins(&data, &diagonal).1.len(), 1);
     }
 }
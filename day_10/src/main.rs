@@ -1,57 +1,267 @@
-use counter::Counter;
-use general::read_data_lines;
+use general::{read_data_lines, split_on};
 use structopt::StructOpt;
 
 const PUZZLE_NAME: &str = "Advent of Code: Day 10 -- Version:";
 const PUZZLE_ABOUT: &str = "Syntax Scoring: https://adventofcode.com/2021/day/10";
 
-fn get_solutions(data: &[String]) -> (u64, u64) {
-    let mut illegal = Counter::<char, u64>::new();
-    let mut scores = vec![];
-    for line in data {
-        let mut stack = vec![];
-        let mut corrupt_line = false;
-        for c in line.chars() {
-            match c {
-                '(' | '[' | '{' | '<' => stack.push(c),
-                ')' | ']' | '}' | '>' => {
-                    let p = stack.pop();
-                    if p.is_none()
-                        || p == Some('(') && c != ')'
-                        || p == Some('[') && c != ']'
-                        || p == Some('{') && c != '}'
-                        || p == Some('<') && c != '>'
-                    {
-                        illegal[&c] += 1;
-                        corrupt_line = true;
-                        break;
+// bracket pairs and their (corrupt, completion) scores
+#[derive(Debug, Clone, PartialEq)]
+struct Syntax {
+    pairs: Vec<(char, char)>,
+    corrupt_scores: Vec<u64>,
+    completion_scores: Vec<u64>,
+    completion_multiplier: u64,
+}
+impl Default for Syntax {
+    fn default() -> Self {
+        Self {
+            pairs: vec![('(', ')'), ('[', ']'), ('{', '}'), ('<', '>')],
+            corrupt_scores: vec![3, 57, 1197, 25137],
+            completion_scores: vec![1, 2, 3, 4],
+            completion_multiplier: 5,
+        }
+    }
+}
+impl Syntax {
+    // pairs is a string of open/close characters, e.g. "()[]{}<>", each character may appear only once
+    fn new(
+        pairs: &str,
+        corrupt_scores: &[u64],
+        completion_scores: &[u64],
+        completion_multiplier: u64,
+    ) -> Result<Self, String> {
+        let chars = pairs.chars().collect::<Vec<_>>();
+        if chars.is_empty() || chars.len() % 2 != 0 {
+            return Err(format!("bracket pairs must be open/close couples: {:?}", pairs));
+        }
+        if let Some((i, c)) = chars.iter().enumerate().find(|(i, c)| chars[..*i].contains(c)) {
+            return Err(format!(
+                "bracket pairs reuse '{}' at position {}: {:?}",
+                c,
+                i + 1,
+                pairs
+            ));
+        }
+        let pairs = chars.chunks(2).map(|p| (p[0], p[1])).collect::<Vec<_>>();
+        if corrupt_scores.len() != pairs.len() || completion_scores.len() != pairs.len() {
+            return Err(format!("expected {} scores per table", pairs.len()));
+        }
+        Ok(Self {
+            pairs,
+            corrupt_scores: corrupt_scores.to_vec(),
+            completion_scores: completion_scores.to_vec(),
+            completion_multiplier,
+        })
+    }
+
+    fn opener(&self, c: char) -> Option<usize> {
+        self.pairs.iter().position(|p| p.0 == c)
+    }
+
+    fn closer(&self, c: char) -> Option<usize> {
+        self.pairs.iter().position(|p| p.1 == c)
+    }
+}
+
+// line and column numbers are 1-based
+#[derive(Debug, Clone, PartialEq)]
+enum Diagnostic {
+    Valid {
+        line: usize,
+    },
+    Corrupted {
+        line: usize,
+        column: usize,
+        expected: Option<char>,
+        found: char,
+    },
+    Incomplete {
+        line: usize,
+        completion: String,
+    },
+    UnknownChar {
+        line: usize,
+        column: usize,
+        found: char,
+    },
+}
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Diagnostic::Valid { line } => write!(f, "line {}: valid", line),
+            Diagnostic::Corrupted {
+                line,
+                column,
+                expected: Some(e),
+                found,
+            } => write!(
+                f,
+                "line {}, column {}: expected '{}', found '{}'",
+                line, column, e, found
+            ),
+            Diagnostic::Corrupted {
+                line, column, found, ..
+            } => write!(f, "line {}, column {}: unexpected '{}'", line, column, found),
+            Diagnostic::Incomplete { line, completion } => {
+                write!(f, "line {}: incomplete, complete with \"{}\"", line, completion)
+            }
+            Diagnostic::UnknownChar { line, column, found } => {
+                write!(f, "line {}, column {}: unknown char '{}'", line, column, found)
+            }
+        }
+    }
+}
+
+// a matched open/close pair, span holds the 0-based columns of the open and close chars
+#[derive(Debug, Clone, PartialEq)]
+struct Chunk {
+    pair: usize,
+    span: (usize, usize),
+    children: Vec<Chunk>,
+}
+
+// walk the line with a stack of open chunks, returning the diagnostic and the completed top level chunks
+fn parse(line_no: usize, line: &str, syntax: &Syntax) -> (Diagnostic, Vec<Chunk>) {
+    let mut chunks = vec![];
+    let mut stack: Vec<Chunk> = vec![];
+    for (i, c) in line.chars().enumerate() {
+        if let Some(p) = syntax.opener(c) {
+            stack.push(Chunk {
+                pair: p,
+                span: (i, i),
+                children: vec![],
+            });
+        } else if syntax.closer(c).is_some() {
+            match stack.pop() {
+                Some(mut chunk) if syntax.pairs[chunk.pair].1 == c => {
+                    chunk.span.1 = i;
+                    match stack.last_mut() {
+                        Some(parent) => parent.children.push(chunk),
+                        None => chunks.push(chunk),
                     }
                 }
-                _ => panic!("{}", format!("unknown char: {}", c)),
+                chunk => {
+                    let diagnostic = Diagnostic::Corrupted {
+                        line: line_no,
+                        column: i + 1,
+                        expected: chunk.map(|chunk| syntax.pairs[chunk.pair].1),
+                        found: c,
+                    };
+                    return (diagnostic, chunks);
+                }
             }
+        } else {
+            let diagnostic = Diagnostic::UnknownChar {
+                line: line_no,
+                column: i + 1,
+                found: c,
+            };
+            return (diagnostic, chunks);
         }
+    }
 
-        if !corrupt_line {
-            let mut score = 0;
-            while let Some(c) = stack.pop() {
-                score *= 5;
-                match c {
-                    '(' => score += 1,
-                    '[' => score += 2,
-                    '{' => score += 3,
-                    '<' => score += 4,
-                    _ => panic!("{}", format!("unexpected char: {}", c)),
-                }
+    let diagnostic = match stack.is_empty() {
+        true => Diagnostic::Valid { line: line_no },
+        false => Diagnostic::Incomplete {
+            line: line_no,
+            completion: stack.iter().rev().map(|chunk| syntax.pairs[chunk.pair].1).collect(),
+        },
+    };
+    (diagnostic, chunks)
+}
+
+fn validate(line_no: usize, line: &str, syntax: &Syntax) -> Diagnostic {
+    parse(line_no, line, syntax).0
+}
+
+// the nesting tree of a valid line, or the diagnostic explaining why it isn't valid
+fn get_chunks(line_no: usize, line: &str, syntax: &Syntax) -> Result<Vec<Chunk>, Diagnostic> {
+    match parse(line_no, line, syntax) {
+        (Diagnostic::Valid { .. }, chunks) => Ok(chunks),
+        (diagnostic, _) => Err(diagnostic),
+    }
+}
+
+fn max_depth(chunks: &[Chunk]) -> usize {
+    chunks
+        .iter()
+        .map(|chunk| 1 + max_depth(&chunk.children))
+        .max()
+        .unwrap_or(0)
+}
+
+// number of chunks of each bracket type, indexed like syntax.pairs
+fn chunk_counts(chunks: &[Chunk], syntax: &Syntax) -> Vec<usize> {
+    let mut counts = vec![0; syntax.pairs.len()];
+    let mut todo = chunks.iter().collect::<Vec<_>>();
+    while let Some(chunk) = todo.pop() {
+        counts[chunk.pair] += 1;
+        todo.extend(chunk.children.iter());
+    }
+    counts
+}
+
+// one line per bracket, children indented under their parent, empty chunks on a single line
+fn pretty_print(chunks: &[Chunk], syntax: &Syntax, indent: usize) -> String {
+    let mut out = String::new();
+    for chunk in chunks {
+        let (open, close) = syntax.pairs[chunk.pair];
+        let pad = "  ".repeat(indent);
+        match chunk.children.is_empty() {
+            true => out += &format!("{}{}{}\n", pad, open, close),
+            false => {
+                out += &format!("{}{}\n", pad, open);
+                out += &pretty_print(&chunk.children, syntax, indent + 1);
+                out += &format!("{}{}\n", pad, close);
+            }
+        }
+    }
+    out
+}
+
+fn get_diagnostics(data: &[String], syntax: &Syntax) -> Vec<Diagnostic> {
+    data.iter()
+        .enumerate()
+        .map(|(i, line)| validate(i + 1, line, syntax))
+        .collect()
+}
+
+// the mean of the two middle values (rounded down) when there is an even count
+fn median(values: &mut [u64]) -> u64 {
+    values.sort_unstable();
+    match values.len() {
+        0 => 0,
+        n if n % 2 == 1 => values[n / 2],
+        n => {
+            let (a, b) = (values[n / 2 - 1], values[n / 2]);
+            a + (b - a) / 2
+        }
+    }
+}
+
+// scores are checked so a long enough completion reports an overflow rather than panicking
+fn get_solutions(data: &[String], syntax: &Syntax) -> Result<(u64, u64), String> {
+    let mut corrupt_score = 0u64;
+    let mut scores = vec![];
+    for diagnostic in get_diagnostics(data, syntax) {
+        match diagnostic {
+            Diagnostic::Corrupted { line, found, .. } => {
+                corrupt_score = corrupt_score
+                    .checked_add(syntax.corrupt_scores[syntax.closer(found).unwrap()])
+                    .ok_or(format!("line {}: corrupted score overflows u64", line))?;
             }
-            scores.push(score);
+            Diagnostic::Incomplete { line, completion } => {
+                let score = completion.chars().try_fold(0u64, |score, c| {
+                    score
+                        .checked_mul(syntax.completion_multiplier)?
+                        .checked_add(syntax.completion_scores[syntax.closer(c).unwrap()])
+                });
+                scores.push(score.ok_or(format!("line {}: completion score overflows u64", line))?);
+            }
+            _ => {}
         }
     }
-    assert_eq!(scores.len() % 2, 1, "scores must be and odd number: {}", scores.len());
-    scores.sort_unstable();
-    (
-        3 * illegal[&')'] + 57 * illegal[&']'] + 1197 * illegal[&'}'] + 25137 * illegal[&'>'],
-        scores[scores.len() / 2],
-    )
+    Ok((corrupt_score, median(&mut scores)))
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -65,13 +275,62 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             help = "file|stdin -- puzzle input"
         )]
         input: Option<std::path::PathBuf>,
+
+        #[structopt(short, long, help = "print a diagnostic for every line")]
+        diagnostics: bool,
+
+        #[structopt(short, long, help = "print the chunk tree of every valid line")]
+        tree: bool,
+
+        #[structopt(long, default_value = "()[]{}<>", help = "bracket pairs, open then close")]
+        pairs: String,
+
+        #[structopt(long, default_value = "3,57,1197,25137", help = "corrupt scores, one per pair")]
+        corrupt_scores: String,
+
+        #[structopt(long, default_value = "1,2,3,4", help = "completion scores, one per pair")]
+        completion_scores: String,
+
+        #[structopt(
+            long,
+            default_value = "5",
+            help = "factor applied to a completion score before each char"
+        )]
+        completion_multiplier: u64,
     }
     let args = Cli::from_args();
 
     // ==============================================================
 
     let data = read_data_lines::<String>(args.input)?;
-    let (p1, p2) = get_solutions(&data);
+    let syntax = Syntax::new(
+        &args.pairs,
+        &split_on::<u64>(&args.corrupt_scores, ',')?,
+        &split_on::<u64>(&args.completion_scores, ',')?,
+        args.completion_multiplier,
+    )?;
+
+    if args.diagnostics {
+        for diagnostic in get_diagnostics(&data, &syntax) {
+            println!("{}", diagnostic);
+        }
+    }
+
+    if args.tree {
+        for (i, line) in data.iter().enumerate() {
+            if let Ok(chunks) = get_chunks(i + 1, line, &syntax) {
+                let counts = chunk_counts(&chunks, &syntax)
+                    .iter()
+                    .zip(&syntax.pairs)
+                    .map(|(n, (open, close))| format!("{}{} = {}", open, close, n))
+                    .collect::<Vec<_>>();
+                println!("line {}: depth {}, {}", i + 1, max_depth(&chunks), counts.join(", "));
+                print!("{}", pretty_print(&chunks, &syntax, 1));
+            }
+        }
+    }
+
+    let (p1, p2) = get_solutions(&data, &syntax)?;
     println!("Answer Part 1 = {}", p1);
     println!("Answer Part 2 = {}", p2);
     Ok(())
@@ -89,24 +348,118 @@ mod tests {
     #[test]
     fn part1_example() {
         let data = get_data("input-example");
-        assert_eq!(get_solutions(&data).0, 26397);
+        assert_eq!(get_solutions(&data, &Syntax::default()).unwrap().0, 26397);
     }
 
     #[test]
     fn part1_actual() {
         let data = get_data("input-actual");
-        assert_eq!(get_solutions(&data).0, 464991);
+        assert_eq!(get_solutions(&data, &Syntax::default()).unwrap().0, 464991);
     }
 
     #[test]
     fn part2_example() {
         let data = get_data("input-example");
-        assert_eq!(get_solutions(&data).1, 288957);
+        assert_eq!(get_solutions(&data, &Syntax::default()).unwrap().1, 288957);
     }
 
     #[test]
     fn part2_actual() {
         let data = get_data("input-actual");
-        assert_eq!(get_solutions(&data).1, 3662008566);
+        assert_eq!(get_solutions(&data, &Syntax::default()).unwrap().1, 3662008566);
+    }
+
+    #[test]
+    fn diagnostics_example() {
+        let data = get_data("input-example");
+        let diagnostics = get_diagnostics(&data, &Syntax::default());
+        assert_eq!(
+            diagnostics[0],
+            Diagnostic::Incomplete {
+                line: 1,
+                completion: "}}]])})]".to_string()
+            }
+        );
+        assert_eq!(
+            diagnostics[2],
+            Diagnostic::Corrupted {
+                line: 3,
+                column: 13,
+                expected: Some(']'),
+                found: '}'
+            }
+        );
+    }
+
+    #[test]
+    fn diagnostics_edge_cases() {
+        let syntax = Syntax::default();
+        assert_eq!(validate(1, "()", &syntax), Diagnostic::Valid { line: 1 });
+        assert_eq!(
+            validate(2, ")", &syntax),
+            Diagnostic::Corrupted {
+                line: 2,
+                column: 1,
+                expected: None,
+                found: ')'
+            }
+        );
+        assert_eq!(
+            validate(3, "(x)", &syntax),
+            Diagnostic::UnknownChar {
+                line: 3,
+                column: 2,
+                found: 'x'
+            }
+        );
+
+        let syntax = Syntax::new("ab", &[7], &[1], 5).unwrap();
+        let data = vec!["aab".to_string(), "aaab".to_string(), "ba".to_string()];
+        assert_eq!(get_solutions(&data, &syntax).unwrap(), (7, 3));
+        let syntax = Syntax::new("ab", &[7], &[1], 2).unwrap();
+        assert_eq!(get_solutions(&data, &syntax).unwrap(), (7, 2));
+    }
+
+    #[test]
+    fn ambiguous_pairs() {
+        assert!(Syntax::new("()||", &[1, 2], &[1, 2], 5).is_err());
+        assert!(Syntax::new("()(]", &[1, 2], &[1, 2], 5).is_err());
+        assert!(Syntax::new("()[)", &[1, 2], &[1, 2], 5).is_err());
+        assert_eq!(
+            Syntax::new("||", &[1], &[1], 5),
+            Err("bracket pairs reuse '|' at position 2: \"||\"".to_string())
+        );
+        assert_eq!(
+            Syntax::new("()[]{}<>", &[3, 57, 1197, 25137], &[1, 2, 3, 4], 5),
+            Ok(Syntax::default())
+        );
+    }
+
+    #[test]
+    fn chunk_tree() {
+        let syntax = Syntax::default();
+        let chunks = get_chunks(1, "[<>({}){}[([])<>]]()", &syntax).unwrap();
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].span, (0, 17));
+        assert_eq!(chunks[1].span, (18, 19));
+        assert_eq!(max_depth(&chunks), 4);
+        assert_eq!(chunk_counts(&chunks, &syntax), vec![3, 3, 2, 2]);
+        assert_eq!(
+            pretty_print(&get_chunks(1, "(<>[])", &syntax).unwrap(), &syntax, 0),
+            "(\n  <>\n  []\n)\n"
+        );
+        assert!(get_chunks(2, "(]", &syntax).is_err());
+    }
+
+    #[test]
+    fn long_completion() {
+        // 27 ">" score 4 * (5^26 + ... + 1) = 5^27 - 1 which still fits in u64
+        let data = vec!["<".repeat(27)];
+        assert_eq!(get_solutions(&data, &Syntax::default()).unwrap().1, 5u64.pow(27) - 1);
+        let data = vec!["(".to_string(), "<".repeat(30)];
+        assert_eq!(
+            get_solutions(&data, &Syntax::default()),
+            Err("line 2: completion score overflows u64".to_string())
+        );
     }
 }
@@ -1,13 +1,17 @@
+use general::frames::{FrameFormat, FrameWriter};
 use general::read_data_lines;
 use ndarray::{Array, Array2, ArrayView};
+use std::collections::{HashSet, VecDeque};
 use structopt::StructOpt;
 
 const PUZZLE_NAME: &str = "Advent of Code: Day 11 -- Version:";
 const PUZZLE_ABOUT: &str = "Dumbo Octopus: https://adventofcode.com/2021/day/11";
 
-fn get_adjacents(r: usize, c: usize) -> Vec<(usize, usize)> {
-    let (i, j) = (r as i32, c as i32);
-    let range = &0..=&9;
+const STEP_LIMIT: usize = 100_000; // steps searched for the first synchronized flash
+
+fn get_adjacents(energy_levels: &Array2<u32>, r: usize, c: usize) -> Vec<(usize, usize)> {
+    let (i, j) = (r as i64, c as i64);
+    let (nrows, ncols) = (energy_levels.nrows() as i64, energy_levels.ncols() as i64);
     [
         (i - 1, j),
         (i + 1, j),
@@ -19,62 +23,122 @@ fn get_adjacents(r: usize, c: usize) -> Vec<(usize, usize)> {
         (i + 1, j + 1),
     ]
     .iter()
-    .filter(|(r, c)| range.contains(&r) && range.contains(&c))
+    .filter(|(r, c)| (0..nrows).contains(r) && (0..ncols).contains(c))
     .map(|(r, c)| (*r as usize, *c as usize))
     .collect::<Vec<(_, _)>>()
 }
 
-// increase each energy level and return the flash count
-// flash count increases by 1 when an energy level increases
-// from 9 to 10 and are only counted once.
-fn increase_energy(r: usize, c: usize, energy_levels: &mut Array2<u32>) -> usize {
-    match energy_levels[[r, c]] == 9 {
-        true => {
-            energy_levels[[r, c]] = 10;
-            1 + get_adjacents(r, c)
-                .iter()
-                .map(|(i, j)| increase_energy(*i, *j, energy_levels))
-                .sum::<usize>()
+// a grid of octopuses of any size, `steps` counts the steps taken so far
+#[derive(Debug, Clone, PartialEq)]
+struct Cavern {
+    energy_levels: Array2<u32>,
+    steps: usize,
+}
+impl Cavern {
+    fn new(energy_levels: &Array2<u32>) -> Self {
+        Self {
+            energy_levels: energy_levels.clone(),
+            steps: 0,
         }
-        false => {
-            if energy_levels[[r, c]] != 10 {
-                energy_levels[[r, c]] += 1;
+    }
+
+    // advance one step and return the cells in the order they flashed
+    // flashes propagate through a worklist rather than recursion so huge cascades can't blow the stack
+    fn step_order(&mut self) -> Vec<(usize, usize)> {
+        self.steps += 1;
+
+        // an energy level of 10 marks a cell that has flashed this step
+        let mut worklist = VecDeque::new();
+        for ((r, c), elem) in self.energy_levels.indexed_iter_mut() {
+            *elem += 1;
+            if *elem == 10 {
+                worklist.push_back((r, c));
             }
-            0
         }
-    }
-}
 
-fn solution(energy_levels: &mut Array2<u32>) -> (usize, usize) {
-    let mut flash_count = 0;
-    let mut step = 0;
-    let mut all_flashed = 0;
-    while step < 100 || all_flashed == 0 {
-        step += 1;
-        for r in 0..=9 {
-            for c in 0..=9 {
-                let increase = increase_energy(r, c, energy_levels);
-                // count number of flashes for the first 100 steps
-                if step <= 100 {
-                    flash_count += increase;
+        let mut flashed = vec![];
+        while let Some((r, c)) = worklist.pop_front() {
+            flashed.push((r, c));
+            for (i, j) in get_adjacents(&self.energy_levels, r, c) {
+                if self.energy_levels[[i, j]] < 10 {
+                    self.energy_levels[[i, j]] += 1;
+                    if self.energy_levels[[i, j]] == 10 {
+                        worklist.push_back((i, j));
+                    }
                 }
             }
         }
 
         // reset flashed items back to zero
-        for elem in energy_levels.iter_mut() {
-            if *elem == 10 {
-                *elem = 0;
+        for &(r, c) in &flashed {
+            self.energy_levels[[r, c]] = 0;
+        }
+        flashed
+    }
+
+    // advance one step and return the cells that flashed
+    fn step(&mut self) -> HashSet<(usize, usize)> {
+        self.step_order().into_iter().collect()
+    }
+
+    // an endless iterator of the cells flashed by each step
+    fn steps(&mut self) -> impl Iterator<Item = HashSet<(usize, usize)>> + '_ {
+        std::iter::from_fn(move || Some(self.step()))
+    }
+
+    fn flashes_after(&mut self, n: usize) -> usize {
+        self.steps().take(n).map(|flashed| flashed.len()).sum()
+    }
+
+    // the step number where every octopus flashes, giving up after `limit` more steps
+    // or once the grid repeats a state, since the steps are deterministic it then cycles forever
+    fn first_all_flash(&mut self, limit: usize) -> Option<usize> {
+        let size = self.energy_levels.len();
+        let mut seen = HashSet::new();
+        for _ in 0..limit {
+            if !seen.insert(self.energy_levels.clone()) {
+                return None;
+            }
+            if self.step().len() == size {
+                return Some(self.steps);
             }
         }
+        None
+    }
+}
 
-        // if the energy sum is zero, 100% flashed, record the step
-        if all_flashed == 0 && energy_levels.sum() == 0 {
-            all_flashed = step;
+// octopuses that just flashed are white, the rest dim with their energy level
+fn write_frame(cavern: &Cavern, writer: &mut FrameWriter) -> std::io::Result<()> {
+    let (nrows, ncols) = cavern.energy_levels.dim();
+    writer.write(nrows, ncols, |r, c| {
+        let level = cavern.energy_levels[[r, c]];
+        let glyph = char::from_digit(level, 10).unwrap_or('*');
+        match level {
+            0 => (glyph, [255, 255, 255]),
+            _ => (glyph, [0, (level * 12) as u8, (level * 20) as u8]),
         }
+    })?;
+    Ok(())
+}
+
+// write the starting grid and each of the next `steps` steps as frames
+fn export_frames(energy_levels: &Array2<u32>, steps: usize, writer: &mut FrameWriter) -> std::io::Result<()> {
+    let mut cavern = Cavern::new(energy_levels);
+    write_frame(&cavern, writer)?;
+    for _ in 0..steps {
+        cavern.step();
+        write_frame(&cavern, writer)?;
     }
+    Ok(())
+}
+
+fn solution1(energy_levels: &Array2<u32>) -> usize {
+    Cavern::new(energy_levels).flashes_after(100)
+}
 
-    (flash_count, all_flashed)
+// None when the octopuses never all flash together
+fn solution2(energy_levels: &Array2<u32>) -> Option<usize> {
+    Cavern::new(energy_levels).first_all_flash(STEP_LIMIT)
 }
 
 fn get_energy_levels(data: &[String]) -> Array2<u32> {
@@ -106,15 +170,35 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             help = "file|stdin -- puzzle input"
         )]
         input: Option<std::path::PathBuf>,
+
+        #[structopt(long, parse(from_os_str), help = "write a frame per step into this directory")]
+        frames: Option<std::path::PathBuf>,
+
+        #[structopt(long, help = "write frames as PPM images instead of text")]
+        ppm: bool,
     }
     let args = Cli::from_args();
 
     // ==============================================================
 
     let data = read_data_lines::<String>(args.input)?;
-    let (p1, p2) = solution(&mut get_energy_levels(&data));
-    println!("Answer Part 1 = {}", p1);
-    println!("Answer Part 2 = {}", p2);
+    let energy_levels = get_energy_levels(&data);
+    println!("Answer Part 1 = {}", solution1(&energy_levels));
+    let p2 = solution2(&energy_levels);
+    match p2 {
+        Some(step) => println!("Answer Part 2 = {}", step),
+        None => println!("Answer Part 2 = never"),
+    }
+
+    // cover both parts: the first 100 steps and up to the first synchronized flash
+    if let Some(dir) = args.frames {
+        let format = match args.ppm {
+            true => FrameFormat::Ppm,
+            false => FrameFormat::Text,
+        };
+        let mut writer = FrameWriter::new(dir, format)?;
+        export_frames(&energy_levels, p2.unwrap_or(0).max(100), &mut writer)?;
+    }
     Ok(())
 }
 
@@ -130,28 +214,80 @@ mod tests {
     #[test]
     fn part1_example() {
         let data = get_data("input-example");
-        let mut energy_levels = get_energy_levels(&data);
-        assert_eq!(solution(&mut energy_levels).0, 1656);
+        let energy_levels = get_energy_levels(&data);
+        assert_eq!(solution1(&energy_levels), 1656);
     }
 
     #[test]
     fn part1_actual() {
         let data = get_data("input-actual");
-        let mut energy_levels = get_energy_levels(&data);
-        assert_eq!(solution(&mut energy_levels).0, 1655);
+        let energy_levels = get_energy_levels(&data);
+        assert_eq!(solution1(&energy_levels), 1655);
     }
 
     #[test]
     fn part2_example() {
         let data = get_data("input-example");
-        let mut energy_levels = get_energy_levels(&data);
-        assert_eq!(solution(&mut energy_levels).1, 195);
+        let energy_levels = get_energy_levels(&data);
+        assert_eq!(solution2(&energy_levels), Some(195));
     }
 
     #[test]
     fn part2_actual() {
         let data = get_data("input-actual");
-        let mut energy_levels = get_energy_levels(&data);
-        assert_eq!(solution(&mut energy_levels).1, 337);
+        let energy_levels = get_energy_levels(&data);
+        assert_eq!(solution2(&energy_levels), Some(337));
+    }
+
+    #[test]
+    fn non_square_cavern() {
+        let data = vec!["11111".to_string(), "19991".to_string(), "11111".to_string()];
+        let mut cavern = Cavern::new(&get_energy_levels(&data));
+        assert_eq!(cavern.step().len(), 3);
+        assert_eq!(
+            cavern.energy_levels,
+            get_energy_levels(&["34543", "30003", "34543"].map(String::from))
+        );
+        assert_eq!(cavern.steps().take(5).count(), 5);
+        assert_eq!(cavern.steps, 6);
+    }
+
+    #[test]
+    fn never_all_flash() {
+        for data in [vec!["05"], vec!["0123456789"], vec!["0000000005"], vec!["1", "2", "5"]] {
+            let energy_levels = get_energy_levels(&data.iter().map(|s| s.to_string()).collect::<Vec<_>>());
+            assert_eq!(solution2(&energy_levels), None, "{:?}", data);
+        }
+        // every octopus of a 2x2 grid is adjacent to the others, so one flash sets them all off
+        assert_eq!(solution2(&get_energy_levels(&["12", "34"].map(String::from))), Some(6));
+    }
+
+    #[test]
+    fn flash_order() {
+        let data = vec!["11111".to_string(), "19991".to_string(), "11111".to_string()];
+        let mut cavern = Cavern::new(&get_energy_levels(&data));
+        assert_eq!(cavern.step_order(), vec![(1, 1), (1, 2), (1, 3)]);
+    }
+
+    #[test]
+    fn large_cascade() {
+        let energy_levels = Array2::from_elem((1000, 1000), 9);
+        let mut cavern = Cavern::new(&energy_levels);
+        assert_eq!(cavern.step().len(), 1_000_000);
+        assert_eq!(cavern.energy_levels.sum(), 0);
+    }
+
+    #[test]
+    fn frames_example() {
+        let energy_levels = get_energy_levels(&get_data("input-example"));
+        let dir = std::env::temp_dir().join(format!("day_11_frames_{}", std::process::id()));
+        let mut writer = FrameWriter::new(&dir, FrameFormat::Ppm).unwrap();
+        export_frames(&energy_levels, 2, &mut writer).unwrap();
+        assert_eq!(writer.frames(), 3);
+
+        let ppm = std::fs::read(dir.join("frame_000002.ppm")).unwrap();
+        assert!(ppm.starts_with(b"P6\n10 10\n255\n"));
+        assert_eq!(ppm.len(), 13 + 10 * 10 * 3);
+        std::fs::remove_dir_all(dir).unwrap();
     }
 }
@@ -1,5 +1,6 @@
 use general::read_data_lines;
-use std::collections::{BTreeMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::io::{BufWriter, Write};
 use structopt::StructOpt;
 
 const PUZZLE_NAME: &str = "Advent of Code: Day 12 -- Version:";
@@ -44,60 +45,265 @@ fn is_small(s: &str) -> bool {
     s.to_lowercase() == s
 }
 
-fn visit(
-    graph: &BTreeMap<String, HashSet<String>>,
-    node: &str,
-    special: &str,
-    max_count: usize,
-    visited: &mut BTreeMap<String, usize>,
-    paths: &mut Vec<String>,
-    solutions: &mut HashSet<Vec<String>>,
-) {
-    if node == "end" {
-        //println!("paths = {:?}", paths);
-        solutions.insert(paths.to_vec());
-        return;
-    }
-
-    if is_small(node) {
-        let count = visited.entry(node.to_string()).or_insert(0);
-        *count += 1;
-    }
-
-    match graph.get(node) {
-        Some(items) => {
-            for item in items {
-                if !visited.contains_key(item) || (item == special && visited.get(special) < Some(&max_count)) {
-                    paths.push(item.to_string());
-                    visit(graph, item, special, max_count, visited, paths, solutions);
-                    paths.pop();
-                    if let Some(count) = visited.get_mut(item) {
-                        *count -= 1;
-                        if *count == 0 {
-                            visited.remove(item);
-                        }
+// caves interned to integer ids, small caves take the low ids so a visited set fits in a bitmask
+#[derive(Debug, Clone, PartialEq)]
+struct CaveSystem {
+    names: Vec<String>,
+    adjacents: Vec<Vec<usize>>,
+    nsmall: usize,
+    start: usize,
+    end: usize,
+}
+
+fn get_cave_system(graph: &BTreeMap<String, HashSet<String>>) -> CaveSystem {
+    let mut names = graph
+        .iter()
+        .flat_map(|(k, v)| std::iter::once(k).chain(v.iter()))
+        .cloned()
+        .collect::<Vec<_>>();
+    names.sort_by_key(|name| (!is_small(name), name.clone()));
+    names.dedup();
+
+    let nsmall = names.iter().filter(|name| is_small(name)).count();
+    assert!(nsmall <= 64, "too many small caves for a u64 bitmask: {}", nsmall);
+
+    let id = |name: &str| names.iter().position(|n| n == name).unwrap();
+    let mut adjacents = vec![vec![]; names.len()];
+    for (k, v) in graph {
+        let mut items = v.iter().map(|item| id(item)).collect::<Vec<_>>();
+        items.sort_unstable();
+        adjacents[id(k)] = items;
+    }
+
+    CaveSystem {
+        start: id("start"),
+        end: id("end"),
+        names,
+        adjacents,
+        nsmall,
+    }
+}
+
+// two adjacent large caves would allow endless back and forth, so there's no finite set of paths
+fn assert_no_adjacent_large_caves(caves: &CaveSystem) {
+    for (node, items) in caves.adjacents.iter().enumerate().skip(caves.nsmall) {
+        if let Some(&item) = items.iter().find(|&&item| item >= caves.nsmall) {
+            panic!(
+                "large caves {} and {} are adjacent, infinite paths",
+                caves.names[node], caves.names[item]
+            );
+        }
+    }
+}
+
+// small cave -> (small cave, number of ways to step there directly or through one large cave)
+fn contract_large_caves(caves: &CaveSystem) -> Vec<Vec<(usize, u128)>> {
+    assert_no_adjacent_large_caves(caves);
+    let mut contracted = vec![vec![]; caves.nsmall];
+    for (node, edges) in contracted.iter_mut().enumerate() {
+        let mut ways = BTreeMap::new();
+        for &item in &caves.adjacents[node] {
+            match item < caves.nsmall {
+                true => *ways.entry(item).or_insert(0) += 1,
+                false => {
+                    for &next in &caves.adjacents[item] {
+                        *ways.entry(next).or_insert(0) += 1;
                     }
                 }
             }
         }
-        None => panic!("{}", format!("expected node = {} to be in graph", node)),
+        *edges = ways.into_iter().collect();
+    }
+    contracted
+}
+
+fn count_paths(
+    caves: &CaveSystem,
+    contracted: &[Vec<(usize, u128)>],
+    node: usize,
+    visited: u64,
+    revisit_used: bool,
+    memo: &mut HashMap<(usize, u64, bool), u128>,
+) -> u128 {
+    if node == caves.end {
+        return 1;
+    }
+    if let Some(&count) = memo.get(&(node, visited, revisit_used)) {
+        return count;
+    }
+
+    let mut count = 0;
+    for &(next, ways) in &contracted[node] {
+        if next == caves.start {
+            continue;
+        }
+        count += match visited & (1 << next) != 0 {
+            true if revisit_used => 0,
+            true => ways * count_paths(caves, contracted, next, visited, true, memo),
+            false => ways * count_paths(caves, contracted, next, visited | (1 << next), revisit_used, memo),
+        };
+    }
+    memo.insert((node, visited, revisit_used), count);
+    count
+}
+
+// count = 1 visits small caves at most once, count = 2 allows a single small cave to be visited twice
+fn solution(graph: &BTreeMap<String, HashSet<String>>, count: usize) -> u128 {
+    let caves = get_cave_system(graph);
+    let contracted = contract_large_caves(&caves);
+    let mut memo = HashMap::new();
+    count_paths(&caves, &contracted, caves.start, 1 << caves.start, count < 2, &mut memo)
+}
+
+// Graphviz rendering of the cave system, small caves are circles, large caves filled boxes
+// an optional path (start,A,b,end) has its caves and passages drawn in red
+fn get_dot(graph: &BTreeMap<String, HashSet<String>>, highlight: &[String]) -> Result<String, String> {
+    // get_graph drops the edges into start and out of end, so rebuild the undirected passages
+    let mut edges = BTreeSet::new();
+    for (a, items) in graph {
+        for b in items {
+            edges.insert((a.min(b).clone(), a.max(b).clone()));
+        }
+    }
+    let caves = edges
+        .iter()
+        .flat_map(|(a, b)| [a.clone(), b.clone()])
+        .collect::<BTreeSet<_>>();
+
+    let mut path_edges = BTreeSet::new();
+    for pair in highlight.windows(2) {
+        let (a, b) = (&pair[0], &pair[1]);
+        let edge = (a.min(b).clone(), a.max(b).clone());
+        if !edges.contains(&edge) {
+            return Err(format!("no passage between {} and {}", a, b));
+        }
+        path_edges.insert(edge);
+    }
+
+    let mut dot = String::from("graph caves {\n");
+    for cave in &caves {
+        let shape = match (cave.as_str(), is_small(cave)) {
+            ("start" | "end", _) => "shape=doublecircle",
+            (_, true) => "shape=circle",
+            (_, false) => "shape=box, style=filled, fillcolor=lightgray",
+        };
+        let color = match highlight.contains(cave) {
+            true => ", color=red, fontcolor=red",
+            false => "",
+        };
+        dot += &format!("    \"{}\" [{}{}];\n", cave, shape, color);
+    }
+    for edge in &edges {
+        let color = match path_edges.contains(edge) {
+            true => " [color=red, penwidth=2]",
+            false => "",
+        };
+        dot += &format!("    \"{}\" -- \"{}\"{};\n", edge.0, edge.1, color);
+    }
+    dot += "}\n";
+    Ok(dot)
+}
+
+// max_revisits is the number of extra visits a small cave may get,
+// revisited_caves is how many different small caves may take them
+#[derive(Debug, Clone, Default, PartialEq)]
+struct PathOptions {
+    max_revisits: usize,
+    revisited_caves: usize,
+    required: Vec<String>,
+    forbidden: Vec<String>,
+}
+
+// depth first walk of start -> end paths, one path is held in memory at a time
+struct Paths<'a> {
+    caves: &'a CaveSystem,
+    options: &'a PathOptions,
+    required: Vec<Option<usize>>,
+    forbidden: Vec<bool>,
+    path: Vec<usize>,
+    cursor: Vec<usize>,
+    visits: Vec<usize>,
+    revisited: usize,
+}
+impl<'a> Paths<'a> {
+    fn new(caves: &'a CaveSystem, options: &'a PathOptions) -> Self {
+        assert_no_adjacent_large_caves(caves);
+        let id = |name: &String| caves.names.iter().position(|n| n == name);
+        let mut forbidden = vec![false; caves.names.len()];
+        for name in &options.forbidden {
+            if let Some(i) = id(name) {
+                forbidden[i] = true;
+            }
+        }
+
+        let mut visits = vec![0; caves.names.len()];
+        visits[caves.start] = 1;
+        Self {
+            caves,
+            options,
+            required: options.required.iter().map(id).collect(),
+            forbidden,
+            path: vec![caves.start],
+            cursor: vec![0],
+            visits,
+            revisited: 0,
+        }
+    }
+
+    fn can_enter(&self, node: usize) -> bool {
+        if node == self.caves.start || self.forbidden[node] {
+            return false;
+        }
+        match (node < self.caves.nsmall, self.visits[node]) {
+            (false, _) | (true, 0) => true,
+            (true, 1) => self.options.max_revisits > 0 && self.revisited < self.options.revisited_caves,
+            (true, n) => n <= self.options.max_revisits,
+        }
+    }
+
+    fn enter(&mut self, node: usize) {
+        self.path.push(node);
+        self.cursor.push(0);
+        self.visits[node] += 1;
+        if node < self.caves.nsmall && self.visits[node] == 2 {
+            self.revisited += 1;
+        }
+    }
+
+    fn leave(&mut self) {
+        let node = self.path.pop().unwrap();
+        self.cursor.pop();
+        if node < self.caves.nsmall && self.visits[node] == 2 {
+            self.revisited -= 1;
+        }
+        self.visits[node] -= 1;
     }
 }
+impl<'a> Iterator for Paths<'a> {
+    type Item = Vec<&'a str>;
 
-fn solution(graph: &BTreeMap<String, HashSet<String>>, count: usize) -> usize {
-    let mut paths = vec![];
-    let mut visited = BTreeMap::new();
-    let mut solutions = HashSet::new();
-    if count == 1 {
-        visit(graph, "start", "end", count, &mut visited, &mut paths, &mut solutions);
-    } else {
-        for k in graph.keys() {
-            if is_small(k) && k != "start" && k != "end" {
-                visit(graph, "start", k, count, &mut visited, &mut paths, &mut solutions);
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(&node) = self.path.last() {
+            let i = self.cursor.last().copied().unwrap();
+            if node == self.caves.end || i == self.caves.adjacents[node].len() {
+                self.leave();
+                continue;
+            }
+
+            *self.cursor.last_mut().unwrap() += 1;
+            let next = self.caves.adjacents[node][i];
+            if !self.can_enter(next) {
+                continue;
+            }
+            self.enter(next);
+
+            if next == self.caves.end && self.required.iter().all(|r| r.is_some_and(|r| self.visits[r] > 0)) {
+                let caves = self.caves;
+                return Some(self.path.iter().map(|&n| caves.names[n].as_str()).collect());
             }
         }
+        None
     }
-    solutions.len()
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -111,6 +317,31 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             help = "file|stdin -- puzzle input"
         )]
         input: Option<std::path::PathBuf>,
+
+        #[structopt(short, long, help = "stream the paths as start,A,b,end lines")]
+        paths: bool,
+
+        #[structopt(long, default_value = "0", help = "extra visits allowed to a small cave")]
+        max_revisits: usize,
+
+        #[structopt(long, default_value = "1", help = "number of small caves that may be revisited")]
+        revisited_caves: usize,
+
+        #[structopt(long, use_delimiter = true, help = "caves every path must visit")]
+        require: Vec<String>,
+
+        #[structopt(long, use_delimiter = true, help = "caves no path may visit")]
+        forbid: Vec<String>,
+
+        #[structopt(long, parse(from_os_str), help = "write the cave graph in Graphviz DOT format")]
+        dot: Option<std::path::PathBuf>,
+
+        #[structopt(
+            long,
+            use_delimiter = true,
+            help = "path to highlight in the DOT output, e.g. start,A,end"
+        )]
+        highlight: Vec<String>,
     }
     let args = Cli::from_args();
 
@@ -121,6 +352,24 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     //println!("graph = {:?}", graph);
     println!("Answer Part 1 = {}", solution(&graph, 1));
     println!("Answer Part 2 = {}", solution(&graph, 2));
+
+    if let Some(file) = args.dot {
+        std::fs::write(file, get_dot(&graph, &args.highlight)?)?;
+    }
+
+    if args.paths {
+        let caves = get_cave_system(&graph);
+        let options = PathOptions {
+            max_revisits: args.max_revisits,
+            revisited_caves: args.revisited_caves,
+            required: args.require,
+            forbidden: args.forbid,
+        };
+        let mut out = BufWriter::new(std::io::stdout().lock());
+        for path in Paths::new(&caves, &options) {
+            writeln!(out, "{}", path.join(","))?;
+        }
+    }
     Ok(())
 }
 
@@ -188,4 +437,48 @@ mod tests {
         let graph = get_graph(&data);
         assert_eq!(solution(&graph, 2), 92111);
     }
+
+    #[test]
+    fn many_small_caves() {
+        let mut data = vec!["start-A".to_string(), "A-end".to_string()];
+        for i in 0..12 {
+            data.push(format!("A-s{}", i));
+        }
+        let graph = get_graph(&data);
+        assert_eq!(solution(&graph, 1), 1302061345);
+    }
+
+    #[test]
+    fn path_enumeration() {
+        let caves = get_cave_system(&get_graph(&get_data("input-example")));
+        let options = PathOptions::default();
+        assert_eq!(Paths::new(&caves, &options).count(), 10);
+        assert_eq!(Paths::new(&caves, &options).next().unwrap().join(","), "start,b,end");
+
+        let mut options = PathOptions {
+            max_revisits: 1,
+            revisited_caves: 1,
+            ..PathOptions::default()
+        };
+        assert_eq!(Paths::new(&caves, &options).count(), 36);
+
+        options.forbidden = vec!["c".to_string()];
+        options.required = vec!["d".to_string()];
+        let paths = Paths::new(&caves, &options).map(|p| p.join(",")).collect::<Vec<_>>();
+        assert_eq!(paths.len(), 4);
+        assert!(paths.iter().all(|p| p.contains(",d,") && !p.contains(",c,")));
+    }
+
+    #[test]
+    fn dot_export() {
+        let graph = get_graph(&get_data("input-example"));
+        let path = "start,A,b,end".split(',').map(String::from).collect::<Vec<_>>();
+        let dot = get_dot(&graph, &path).unwrap();
+        assert_eq!(dot.matches(" -- ").count(), 7);
+        assert!(dot.contains("    \"A\" [shape=box, style=filled, fillcolor=lightgray, color=red, fontcolor=red];\n"));
+        assert!(dot.contains("    \"c\" [shape=circle];\n"));
+        assert!(dot.contains("    \"A\" -- \"start\" [color=red, penwidth=2];\n"));
+        assert!(dot.contains("    \"A\" -- \"c\";\n"));
+        assert!(get_dot(&graph, &["start".to_string(), "c".to_string()]).is_err());
+    }
 }
@@ -1,14 +1,28 @@
+use general::ocr::read_letters;
 use general::read_data_lines;
-use ndarray::{s, Array2};
+use std::collections::HashSet;
 use structopt::StructOpt;
 
 const PUZZLE_NAME: &str = "Advent of Code: Day 13 -- Version:";
 const PUZZLE_ABOUT: &str = "Transparent Origami: https://adventofcode.com/2021/day/13";
 
-fn get_data(data: &[String]) -> (Array2<u32>, Vec<i32>) {
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Fold {
+    Left(usize),
+    Up(usize),
+}
+
+// the dots on a sheet of width x height, stored sparsely so huge coordinates cost nothing
+#[derive(Debug, Clone, PartialEq)]
+struct Paper {
+    dots: HashSet<(usize, usize)>,
+    width: usize,
+    height: usize,
+}
+
+fn get_data(data: &[String]) -> (Paper, Vec<Fold>) {
     let mut folds = vec![];
-    let mut x = vec![];
-    let mut y = vec![];
+    let mut dots = HashSet::new();
 
     for line in data {
         match line.contains(',') {
@@ -19,16 +33,15 @@ fn get_data(data: &[String]) -> (Array2<u32>, Vec<i32>) {
                     .map(|s| s.to_string().parse::<usize>().unwrap())
                     .collect::<Vec<_>>();
                 assert_eq!(points.len(), 2, "expected 2 points: {:?}", points);
-                x.push(points[0]);
-                y.push(points[1]);
+                dots.insert((points[0], points[1]));
             }
             false => {
                 if !line.is_empty() {
                     let parts = line.trim().split('=').map(|s| s.into()).collect::<Vec<String>>();
                     assert_eq!(parts.len(), 2, "expected 2 parts: {:?}", parts);
                     match parts[0].as_ref() {
-                        "fold along x" => folds.push(-parts[1].parse::<i32>().unwrap()),
-                        "fold along y" => folds.push(parts[1].parse::<i32>().unwrap()),
+                        "fold along x" => folds.push(Fold::Left(parts[1].parse::<usize>().unwrap())),
+                        "fold along y" => folds.push(Fold::Up(parts[1].parse::<usize>().unwrap())),
                         _ => panic!("{}", format!("unknown instruction: {}", parts[0])),
                     };
                 }
@@ -36,59 +49,61 @@ fn get_data(data: &[String]) -> (Array2<u32>, Vec<i32>) {
         }
     }
 
-    let xmax = *x.iter().max().expect("xmax failure");
-    let ymax = *y.iter().max().expect("ymax max failure");
-
-    // create a new Array2
-    let mut paper = Array2::zeros((xmax + 1, ymax + 1));
-
-    for (i, j) in x.into_iter().zip(y.into_iter()) {
-        paper[[i, j]] = 1;
-    }
-    (paper, folds)
+    let width = dots.iter().map(|(x, _)| x + 1).max().expect("xmax failure");
+    let height = dots.iter().map(|(_, y)| y + 1).max().expect("ymax failure");
+    (Paper { dots, width, height }, folds)
 }
 
-fn fold_up(paper: &Array2<u32>, pos: usize) -> Array2<u32> {
-    // copy over elements from paper not being folded
-    let mut folded = paper
-        .slice(s![0..paper.nrows(), 0..pos.max(paper.ncols() - pos - 1)])
-        .to_owned();
-
-    // update with folded items from paper
-    // if the sum > 0 it is set to 1
-    for i in 0..folded.nrows() {
-        for (c, j) in ((pos + 1)..paper.ncols()).enumerate() {
-            let a = j - 2 - 2 * c;
-            folded[[i, a]] = 1.min(folded[[i, a]] + paper[[i, j]]);
-        }
-    }
-    folded
+// fold a line of `len` cells at `pos`, returning the new length and where coordinate `c` lands
+// the folded strip is as long as the longer side, when the far side is longer (a fold before
+// the middle) the paper extends past the old edge and everything shifts to stay >= 0
+fn fold_line(len: usize, pos: usize) -> (usize, impl Fn(usize) -> usize) {
+    let n = pos.max(len.saturating_sub(pos + 1));
+    let offset = n - pos;
+    (n, move |c: usize| match c < pos {
+        true => c + offset,
+        false => pos + n - c,
+    })
 }
 
-fn fold_left(paper: &Array2<u32>, pos: usize) -> Array2<u32> {
-    // copy over elements from paper not being folded
-    let mut folded = paper
-        .slice(s![0..pos.max(paper.nrows() - pos - 1), 0..paper.ncols()])
-        .to_owned();
-
-    // update with folded items from paper
-    // if the sum > 0 it is set to 1
-    for (c, i) in ((pos + 1)..paper.nrows()).enumerate() {
-        let a = i - 2 - 2 * c;
-        for j in 0..folded.ncols() {
-            folded[[a, j]] = 1.min(folded[[a, j]] + paper[[i, j]]);
+fn fold(paper: &Paper, instruction: &Fold) -> Paper {
+    match *instruction {
+        Fold::Left(pos) => {
+            let (width, f) = fold_line(paper.width, pos);
+            Paper {
+                dots: paper
+                    .dots
+                    .iter()
+                    .filter(|d| d.0 != pos)
+                    .map(|&(x, y)| (f(x), y))
+                    .collect(),
+                width,
+                height: paper.height,
+            }
+        }
+        Fold::Up(pos) => {
+            let (height, f) = fold_line(paper.height, pos);
+            Paper {
+                dots: paper
+                    .dots
+                    .iter()
+                    .filter(|d| d.1 != pos)
+                    .map(|&(x, y)| (x, f(y)))
+                    .collect(),
+                width: paper.width,
+                height,
+            }
         }
     }
-    folded
 }
 
-fn get_message(paper: &Array2<u32>) -> String {
+fn get_message(paper: &Paper) -> String {
     let mut message = "".to_string();
-    for row in paper.t().rows() {
-        for elem in row {
-            match elem {
-                0 => message += " ",
-                _ => message += "#",
+    for y in 0..paper.height {
+        for x in 0..paper.width {
+            match paper.dots.contains(&(x, y)) {
+                false => message += " ",
+                true => message += "#",
             };
         }
         message += "\n";
@@ -115,24 +130,25 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let data = read_data_lines::<String>(args.input)?;
     let (mut paper, instructions) = get_data(&data);
 
-    // instructions are < 0 for "left", > 0 for "up"
     for instruction in &instructions[0..1] {
-        paper = match instruction < &0 {
-            true => fold_left(&paper, instruction.abs() as usize),
-            false => fold_up(&paper, *instruction as usize),
-        };
+        paper = fold(&paper, instruction);
     }
 
-    println!("Answer Part 1 = {}", paper.sum());
+    println!("Answer Part 1 = {}", paper.dots.len());
 
     for instruction in &instructions[1..] {
-        paper = match instruction < &0 {
-            true => fold_left(&paper, instruction.abs() as usize),
-            false => fold_up(&paper, *instruction as usize),
-        };
+        paper = fold(&paper, instruction);
     }
 
-    print!("Answer Part 2 =\n{}", get_message(&paper));
+    let message = get_message(&paper);
+    let (letters, unknown) = read_letters(&message);
+    println!("Answer Part 2 = {}", letters);
+    if !unknown.is_empty() {
+        for (i, glyph) in unknown {
+            eprintln!("unrecognized glyph at position {}:\n{}", i, glyph);
+        }
+        print!("{}", message);
+    }
 
     Ok(())
 }
@@ -151,12 +167,9 @@ mod tests {
         let data = get_testdata("input-example");
         let (mut paper, instructions) = get_data(&data);
         for instruction in &instructions[0..1] {
-            paper = match instruction < &0 {
-                true => fold_left(&paper, instruction.abs() as usize),
-                false => fold_up(&paper, *instruction as usize),
-            };
+            paper = fold(&paper, instruction);
         }
-        assert_eq!(paper.sum(), 17);
+        assert_eq!(paper.dots.len(), 17);
     }
 
     #[test]
@@ -164,12 +177,9 @@ mod tests {
         let data = get_testdata("input-actual");
         let (mut paper, instructions) = get_data(&data);
         for instruction in &instructions[0..1] {
-            paper = match instruction < &0 {
-                true => fold_left(&paper, instruction.abs() as usize),
-                false => fold_up(&paper, *instruction as usize),
-            };
+            paper = fold(&paper, instruction);
         }
-        assert_eq!(paper.sum(), 790);
+        assert_eq!(paper.dots.len(), 790);
     }
 
     #[test]
@@ -177,10 +187,7 @@ mod tests {
         let data = get_testdata("input-example");
         let (mut paper, instructions) = get_data(&data);
         for instruction in &instructions {
-            paper = match instruction < &0 {
-                true => fold_left(&paper, instruction.abs() as usize),
-                false => fold_up(&paper, *instruction as usize),
-            };
+            paper = fold(&paper, instruction);
         }
         let message = get_message(&paper);
         let expected = "#####\n#   #\n#   #\n#   #\n#####\n     \n     \n";
@@ -192,13 +199,47 @@ mod tests {
         let data = get_testdata("input-actual");
         let (mut paper, instructions) = get_data(&data);
         for instruction in &instructions {
-            paper = match instruction < &0 {
-                true => fold_left(&paper, instruction.abs() as usize),
-                false => fold_up(&paper, *instruction as usize),
-            };
+            paper = fold(&paper, instruction);
         }
         let message = get_message(&paper);
         let expected = "###   ##  #  # #### ###  ####   ##  ##  \n#  # #  # #  #    # #  # #       # #  # \n#  # #    ####   #  ###  ###     # #    \n###  # ## #  #  #   #  # #       # #    \n#    #  # #  # #    #  # #    #  # #  # \n#     ### #  # #### ###  #     ##   ##  \n";
         assert_eq!(message, expected);
     }
+
+    #[test]
+    fn fold_before_middle() {
+        let data = ["0,0", "1,0", "9,2", "", "fold along x=2"].map(String::from);
+        let (paper, instructions) = get_data(&data);
+        let paper = fold(&paper, &instructions[0]);
+        assert_eq!((paper.width, paper.height), (7, 3));
+        assert_eq!(get_message(&paper), "     ##\n       \n#      \n");
+    }
+
+    #[test]
+    fn huge_coordinates() {
+        let data = ["0,0", "2000000000,4000000000", "", "fold along y=2000000000"].map(String::from);
+        let (paper, instructions) = get_data(&data);
+        let paper = fold(&paper, &instructions[0]);
+        assert_eq!(paper.dots, HashSet::from([(0, 0), (2000000000, 0)]));
+        assert_eq!(paper.height, 2000000000);
+    }
+
+    #[test]
+    fn part2_letters() {
+        let data = get_testdata("input-actual");
+        let (mut paper, instructions) = get_data(&data);
+        for instruction in &instructions {
+            paper = fold(&paper, instruction);
+        }
+        assert_eq!(read_letters(&get_message(&paper)), ("PGHZBFJC".to_string(), vec![]));
+
+        let data = get_testdata("input-example");
+        let (mut paper, instructions) = get_data(&data);
+        for instruction in &instructions {
+            paper = fold(&paper, instruction);
+        }
+        let (letters, unknown) = read_letters(&get_message(&paper));
+        assert_eq!(letters, "?");
+        assert_eq!(unknown, vec![(0, "####\n#...\n#...\n#...\n####".to_string())]);
+    }
 }
@@ -1,68 +1,224 @@
-use counter::Counter;
 use general::read_data_lines;
+use num::{BigUint, Zero};
 use std::collections::HashMap;
 use structopt::StructOpt;
 
 const PUZZLE_NAME: &str = "Advent of Code: Day 14 -- Version:";
 const PUZZLE_ABOUT: &str = "Extended Polymerization: https://adventofcode.com/2021/day/14";
 
-fn get_data(data: &[String]) -> (String, HashMap<String, String>) {
+// line numbers are 1-based
+#[derive(Debug, Clone, PartialEq)]
+enum RuleError {
+    Malformed { line: usize, text: String },
+    Duplicate { line: usize, pair: String },
+    Uncovered { pair: String },
+}
+impl std::fmt::Display for RuleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RuleError::Malformed { line, text } => write!(f, "line {}: malformed rule {:?}", line, text),
+            RuleError::Duplicate { line, pair } => write!(f, "line {}: duplicate rule for {}", line, pair),
+            RuleError::Uncovered { pair } => write!(f, "no rule for reachable pair {}", pair),
+        }
+    }
+}
+
+// rules are "XY -> Z" where Z is one or more chars inserted between X and Y
+fn get_data(data: &[String]) -> Result<(String, HashMap<String, String>), Vec<RuleError>> {
+    let mut errors = vec![];
+    let start = data.first().cloned().unwrap_or_default();
+    if start.is_empty() || start.contains("->") {
+        errors.push(RuleError::Malformed {
+            line: 1,
+            text: start.clone(),
+        });
+    }
+
     let mut productions = HashMap::new();
-    for line in data[1..].iter().filter(|s| !s.is_empty()) {
+    for (i, line) in data.iter().enumerate().skip(1).filter(|(_, s)| !s.is_empty()) {
         let pairs = line.split("->").map(|s| s.trim().to_string()).collect::<Vec<_>>();
-        productions.insert(pairs[0].to_string(), pairs[1].to_string());
-    }
-    (data[0].to_string(), productions)
-}
-
-fn offset_slices(s: &str, n: usize) -> Vec<&str> {
-    (0..s.len() - n + 1).map(|i| &s[i..i + n]).collect()
-}
-
-fn pair_counts(
-    pair: &str,
-    productions: &HashMap<String, String>,
-    cntr: &mut Counter<String, usize>,
-    cache: &mut HashMap<String, Counter<String, usize>>,
-    step: usize,
-) {
-    match productions.get(pair) {
-        Some(rule) => {
-            cntr[rule] += 1;
-            if step > 1 {
-                let left_pair = pair[0..1].to_string() + rule;
-                let right_pair = rule.to_owned() + &pair[1..2];
-                let key = format!("{}:{}:{}", step, left_pair, right_pair);
-                if let Some(saved_cntr) = cache.get(&key) {
-                    for (k, v) in saved_cntr {
-                        cntr[k] += *v;
-                    }
-                } else {
-                    let saved_cntr = cntr.clone();
-                    pair_counts(&left_pair, productions, cntr, cache, step - 1);
-                    pair_counts(&right_pair, productions, cntr, cache, step - 1);
-                    cache.insert(key, cntr.clone() - saved_cntr);
+        let valid = |s: &String| !s.is_empty() && !s.chars().any(char::is_whitespace);
+        if pairs.len() != 2 || pairs[0].chars().count() != 2 || !valid(&pairs[0]) || !valid(&pairs[1]) {
+            errors.push(RuleError::Malformed {
+                line: i + 1,
+                text: line.to_string(),
+            });
+        } else if productions.insert(pairs[0].to_string(), pairs[1].to_string()).is_some() {
+            errors.push(RuleError::Duplicate {
+                line: i + 1,
+                pair: pairs[0].to_string(),
+            });
+        }
+    }
+
+    match errors.is_empty() {
+        true => Ok((start, productions)),
+        false => Err(errors),
+    }
+}
+
+// give every reachable pair without a rule an empty insertion, leaving it unchanged each step
+fn add_identity_rules(start: &str, productions: &mut HashMap<String, String>) {
+    if let Err(errors) = get_pair_space(start, productions) {
+        for error in errors {
+            if let RuleError::Uncovered { pair } = error {
+                productions.insert(pair, "".to_string());
+            }
+        }
+    }
+}
+
+// the polymer as a vector of adjacent pair counts, with pairs interned to indexes
+// transitions[i] lists the pairs that pair i turns into after one insertion step
+#[derive(Debug, Clone, PartialEq)]
+struct PairSpace {
+    pairs: Vec<(char, char)>,
+    transitions: Vec<Vec<usize>>,
+}
+
+// every pair reachable from the starting polymer needs a rule, the uncovered ones are returned as errors
+fn get_pair_space(start: &str, productions: &HashMap<String, String>) -> Result<PairSpace, Vec<RuleError>> {
+    let mut pairs = vec![];
+    let mut index = HashMap::new();
+    let mut intern = |pair: (char, char), pairs: &mut Vec<(char, char)>| {
+        *index.entry(pair).or_insert_with(|| {
+            pairs.push(pair);
+            pairs.len() - 1
+        })
+    };
+
+    let chars = start.chars().collect::<Vec<_>>();
+    for w in chars.windows(2) {
+        intern((w[0], w[1]), &mut pairs);
+    }
+
+    // pairs are discovered as they become reachable, so the loop bound grows as we go
+    let mut transitions = vec![];
+    let mut errors = vec![];
+    let mut i = 0;
+    while i < pairs.len() {
+        let (a, b) = pairs[i];
+        i += 1;
+        let pair = format!("{}{}", a, b);
+        let rule = match productions.get(&pair) {
+            Some(rule) => rule,
+            None => {
+                errors.push(RuleError::Uncovered { pair });
+                transitions.push(vec![]);
+                continue;
+            }
+        };
+        let polymer = std::iter::once(a)
+            .chain(rule.chars())
+            .chain(std::iter::once(b))
+            .collect::<Vec<_>>();
+        transitions.push(polymer.windows(2).map(|w| intern((w[0], w[1]), &mut pairs)).collect());
+    }
+
+    match errors.is_empty() {
+        true => Ok(PairSpace { pairs, transitions }),
+        false => Err(errors),
+    }
+}
+
+fn get_state(start: &str, space: &PairSpace) -> Vec<BigUint> {
+    let mut state = vec![BigUint::zero(); space.pairs.len()];
+    let chars = start.chars().collect::<Vec<_>>();
+    for w in chars.windows(2) {
+        let i = space.pairs.iter().position(|&p| p == (w[0], w[1])).unwrap();
+        state[i] += 1u32;
+    }
+    state
+}
+
+fn step(state: &[BigUint], space: &PairSpace) -> Vec<BigUint> {
+    let mut next = vec![BigUint::zero(); state.len()];
+    for (count, transitions) in state.iter().zip(&space.transitions) {
+        for &j in transitions {
+            next[j] += count;
+        }
+    }
+    next
+}
+
+// square transition matrix, m[to][from] is how many `to` pairs one `from` pair becomes
+fn transition_matrix(space: &PairSpace) -> Vec<Vec<BigUint>> {
+    let n = space.pairs.len();
+    let mut m = vec![vec![BigUint::zero(); n]; n];
+    for (from, transitions) in space.transitions.iter().enumerate() {
+        for &to in transitions {
+            m[to][from] += 1u32;
+        }
+    }
+    m
+}
+
+fn matrix_mul(a: &[Vec<BigUint>], b: &[Vec<BigUint>]) -> Vec<Vec<BigUint>> {
+    let n = a.len();
+    let mut c = vec![vec![BigUint::zero(); n]; n];
+    for i in 0..n {
+        for k in 0..n {
+            if a[i][k].is_zero() {
+                continue;
+            }
+            for j in 0..n {
+                if !b[k][j].is_zero() {
+                    c[i][j] += &a[i][k] * &b[k][j];
                 }
             }
         }
-        None => panic!("Unknown pair = {}", pair),
     }
+    c
 }
 
-fn solution(start: &str, productions: &HashMap<String, String>, steps: usize) -> usize {
-    // initialize counter with starting chars
-    let mut cntr = start.chars().into_iter().map(|c| c.to_string()).collect::<Counter<_>>();
+// advance the state by `steps` using exponentiation by squaring of the transition matrix
+fn matrix_power_steps(state: &[BigUint], space: &PairSpace, steps: usize) -> Vec<BigUint> {
+    let n = state.len();
+    let mut result = (0..n)
+        .map(|i| (0..n).map(|j| BigUint::from((i == j) as u32)).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    let mut base = transition_matrix(space);
+    let mut e = steps;
+    while e > 0 {
+        if e & 1 == 1 {
+            result = matrix_mul(&result, &base);
+        }
+        base = matrix_mul(&base, &base);
+        e >>= 1;
+    }
+    result
+        .iter()
+        .map(|row| row.iter().zip(state).map(|(m, s)| m * s).sum())
+        .collect()
+}
 
-    // initialize a "pair => Counter" memoize cache
-    let mut cache = HashMap::new();
+// each element is counted as the first char of its pairs, plus the (never changing) last char of the polymer
+fn most_minus_least(start: &str, space: &PairSpace, state: &[BigUint]) -> BigUint {
+    let mut counts = HashMap::<char, BigUint>::new();
+    for (pair, count) in space.pairs.iter().zip(state).filter(|(_, count)| !count.is_zero()) {
+        *counts.entry(pair.0).or_default() += count;
+    }
+    if let Some(last) = start.chars().last() {
+        *counts.entry(last).or_default() += 1u32;
+    }
+    let most = counts.values().max().cloned().unwrap_or_default();
+    let least = counts.values().min().cloned().unwrap_or_default();
+    most - least
+}
 
-    for pair in offset_slices(start, 2) {
-        pair_counts(pair, productions, &mut cntr, &mut cache, steps);
+fn solution(start: &str, productions: &HashMap<String, String>, steps: usize) -> BigUint {
+    let space = get_pair_space(start, productions).expect("rules must cover every reachable pair");
+    let mut state = get_state(start, &space);
+    for _ in 0..steps {
+        state = step(&state, &space);
     }
+    most_minus_least(start, &space, &state)
+}
 
-    // sort and return: most frequent - least frequent
-    let by_common = cntr.most_common_ordered();
-    by_common.first().unwrap().1 - by_common.last().unwrap().1
+fn solution_matrix_power(start: &str, productions: &HashMap<String, String>, steps: usize) -> BigUint {
+    let space = get_pair_space(start, productions).expect("rules must cover every reachable pair");
+    let state = matrix_power_steps(&get_state(start, &space), &space, steps);
+    most_minus_least(start, &space, &state)
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -71,15 +227,39 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     struct Cli {
         #[structopt(short, long, parse(from_os_str), help = "file|stdin -- puzzle input")]
         input: Option<std::path::PathBuf>,
+
+        #[structopt(short, long, help = "also report the answer after this many steps")]
+        steps: Option<usize>,
+
+        #[structopt(short, long, help = "advance by matrix power instead of step by step")]
+        matrix: bool,
+
+        #[structopt(long, help = "leave pairs without a rule unchanged instead of rejecting the rules")]
+        identity: bool,
     }
     let args = Cli::from_args();
 
     // ==============================================================
 
     let data = read_data_lines::<String>(args.input)?;
-    let (start, productions) = get_data(&data);
-    println!("Answer Part 1 = {:?}", solution(&start, &productions, 10));
-    println!("Answer Part 2 = {:?}", solution(&start, &productions, 40));
+    let report = |errors: Vec<RuleError>| errors.iter().map(|e| e.to_string()).collect::<Vec<_>>().join("\n");
+    let (start, mut productions) = get_data(&data).map_err(report)?;
+    match args.identity {
+        true => add_identity_rules(&start, &mut productions),
+        false => {
+            get_pair_space(&start, &productions).map_err(report)?;
+        }
+    }
+
+    let solve = match args.matrix {
+        true => solution_matrix_power,
+        false => solution,
+    };
+    println!("Answer Part 1 = {}", solve(&start, &productions, 10));
+    println!("Answer Part 2 = {}", solve(&start, &productions, 40));
+    if let Some(steps) = args.steps {
+        println!("Answer {} steps = {}", steps, solve(&start, &productions, steps));
+    }
     Ok(())
 }
 
@@ -90,31 +270,89 @@ mod tests {
     fn get_testdata(filename: &str) -> (String, HashMap<String, String>) {
         let file = Some(std::path::PathBuf::from(filename));
         let data = read_data_lines::<String>(file).unwrap();
-        let (start, productions) = get_data(&data);
+        let (start, productions) = get_data(&data).unwrap();
         (start, productions)
     }
 
     #[test]
     fn part1_example() {
         let (start, productions) = get_testdata("input-example");
-        assert_eq!(solution(&start, &productions, 10), 1588)
+        assert_eq!(solution(&start, &productions, 10), BigUint::from(1588u64))
     }
 
     #[test]
     fn part1_actual() {
         let (start, productions) = get_testdata("input-actual");
-        assert_eq!(solution(&start, &productions, 10), 2937)
+        assert_eq!(solution(&start, &productions, 10), BigUint::from(2937u64))
     }
 
     #[test]
     fn part2_example() {
         let (start, productions) = get_testdata("input-example");
-        assert_eq!(solution(&start, &productions, 40), 2188189693529)
+        assert_eq!(solution(&start, &productions, 40), BigUint::from(2188189693529u64))
     }
 
     #[test]
     fn part2_actual() {
         let (start, productions) = get_testdata("input-actual");
-        assert_eq!(solution(&start, &productions, 40), 3390034818249)
+        assert_eq!(solution(&start, &productions, 40), BigUint::from(3390034818249u64))
+    }
+
+    #[test]
+    fn matrix_power() {
+        let (start, productions) = get_testdata("input-example");
+        for steps in [0, 1, 10, 40, 123] {
+            assert_eq!(
+                solution_matrix_power(&start, &productions, steps),
+                solution(&start, &productions, steps)
+            );
+        }
+    }
+
+    #[test]
+    fn many_steps() {
+        let (start, productions) = get_testdata("input-example");
+        let answer = solution(&start, &productions, 10000);
+        assert!(answer.bits() > 10000);
+    }
+
+    #[test]
+    fn rule_validation() {
+        let data = ["NNC", "", "NN -> C", "NC -> ", "NN -> B", "N -> C", "CN -> BB"].map(String::from);
+        assert_eq!(
+            get_data(&data).unwrap_err(),
+            vec![
+                RuleError::Malformed {
+                    line: 4,
+                    text: "NC -> ".to_string()
+                },
+                RuleError::Duplicate {
+                    line: 5,
+                    pair: "NN".to_string()
+                },
+                RuleError::Malformed {
+                    line: 6,
+                    text: "N -> C".to_string()
+                },
+            ]
+        );
+
+        let data = ["NNC", "", "NN -> C", "CN -> BB"].map(String::from);
+        let (start, mut productions) = get_data(&data).unwrap();
+        assert_eq!(
+            get_pair_space(&start, &productions).unwrap_err(),
+            vec![
+                RuleError::Uncovered { pair: "NC".to_string() },
+                RuleError::Uncovered { pair: "CB".to_string() },
+                RuleError::Uncovered { pair: "BB".to_string() },
+                RuleError::Uncovered { pair: "BN".to_string() },
+            ]
+        );
+
+        // NNC -> NCNC -> NCBBNC, only the NN and CN pairs grow and B is absent until step 2
+        add_identity_rules(&start, &mut productions);
+        assert_eq!(solution(&start, &productions, 1), BigUint::from(0u64));
+        assert_eq!(solution(&start, &productions, 2), BigUint::from(0u64));
+        assert_eq!(solution_matrix_power(&start, &productions, 2), BigUint::from(0u64));
     }
 }
@@ -1,6 +1,12 @@
+use general::frames::write_ppm;
 use general::read_data_lines;
 use ndarray::{Array, Array2, ArrayView};
-use std::collections::HashSet;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashSet};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::num::NonZeroUsize;
+use std::path::Path;
 use structopt::StructOpt;
 
 const PUZZLE_NAME: &str = "Advent of Code: Day 15 -- Version:";
@@ -24,106 +30,204 @@ fn get_grid(data: &[String]) -> Array2<u32> {
     grid
 }
 
-fn extend_row_right(row: &[u32]) -> Vec<u32> {
-    let mut erow = row.to_vec();
-    for i in 0..4 {
-        erow.extend(row.iter().map(|n| (*n + i) % 9 + 1).collect::<Vec<_>>());
+// a rectangular map of the risk of entering each cell
+trait RiskMap {
+    fn dim(&self) -> (usize, usize);
+    fn risk(&self, position: (usize, usize)) -> u32;
+}
+
+impl<G: RiskMap + ?Sized> RiskMap for &G {
+    fn dim(&self) -> (usize, usize) {
+        (**self).dim()
+    }
+
+    fn risk(&self, position: (usize, usize)) -> u32 {
+        (**self).risk(position)
     }
-    erow
 }
 
-fn get_grid_x5(data: &[String]) -> Array2<u32> {
-    // row parsing rules for data[String]
-    let get_row = |s: &str| {
-        s.chars()
-            .map(|s| s.to_string().parse::<u32>().unwrap())
-            .collect::<Vec<_>>()
+impl RiskMap for Array2<u32> {
+    fn dim(&self) -> (usize, usize) {
+        (self.nrows(), self.ncols())
+    }
+
+    fn risk(&self, position: (usize, usize)) -> u32 {
+        self[position]
+    }
+}
+
+// the grid repeated `factor` times in each direction, every tile to the right or below adds 1
+// to the risk (wrapping 9 back to 1), computed on demand so the expanded map is never stored
+struct TiledGrid<'a> {
+    grid: &'a Array2<u32>,
+    factor: usize,
+}
+
+impl RiskMap for TiledGrid<'_> {
+    fn dim(&self) -> (usize, usize) {
+        (self.grid.nrows() * self.factor, self.grid.ncols() * self.factor)
+    }
+
+    fn risk(&self, position: (usize, usize)) -> u32 {
+        let (nrows, ncols) = self.grid.dim();
+        let (r, c) = position;
+        let base = self.grid[[r % nrows, c % ncols]] as usize;
+        ((base + r / nrows + c / ncols - 1) % 9 + 1) as u32
+    }
+}
+
+const DIRECTIONS: [(i64, i64); 4] = [(0, 1), (1, 0), (-1, 0), (0, -1)];
+
+// (direction index, position) of the neighbours inside a map of size dim
+fn get_adjacents(dim: (usize, usize), position: (usize, usize)) -> Vec<(usize, (usize, usize))> {
+    let (i, j) = (position.0 as i64, position.1 as i64);
+    let rows = 0..dim.0 as i64;
+    let cols = 0..dim.1 as i64;
+    DIRECTIONS
+        .iter()
+        .map(|(dr, dc)| (i + dr, j + dc))
+        .enumerate()
+        .filter(|(_, (r, c))| rows.contains(r) && cols.contains(c))
+        .map(|(d, (r, c))| (d, (r as usize, c as usize)))
+        .collect::<Vec<_>>()
+}
+
+// Dijkstra over the risk of entering each cell, returning the cumulative risk of reaching each cell
+// and 1 + the direction index taken to reach it (0 when unreached), a byte per cell keeps large tilings affordable
+// the search stops once finish is settled, without a finish every reachable cell is settled
+// with `astar` the search is steered by the manhattan distance to finish scaled by the lowest risk in the map,
+// which never overestimates so the path found is still a minimum
+fn search<G: RiskMap>(
+    grid: &G,
+    start: (usize, usize),
+    finish: Option<(usize, usize)>,
+    astar: bool,
+) -> (Array2<u32>, Array2<u8>) {
+    let dim = grid.dim();
+    let min_risk = match (astar, finish) {
+        (true, Some(_)) => (0..dim.0)
+            .flat_map(|r| (0..dim.1).map(move |c| (r, c)))
+            .map(|pt| grid.risk(pt))
+            .min()
+            .unwrap_or(0),
+        _ => 0,
     };
+    let target = finish.unwrap_or(start);
+    let estimate = |(r, c): (usize, usize)| (r.abs_diff(target.0) + c.abs_diff(target.1)) as u32 * min_risk;
 
-    // use data[0] to size the new Array2
-    let row = extend_row_right(&get_row(&data[0]));
-    let mut grid = Array::zeros((0, row.len()));
-    grid.push_row(ArrayView::from(&row)).unwrap();
-
-    // process remaining data[1..]
-    for line in &data[1..] {
-        grid.push_row(ArrayView::from(&extend_row_right(&get_row(line))))
-            .unwrap();
-    }
-    for i in 1..5 {
-        for line in data {
-            let mut row = extend_row_right(&get_row(line));
-            for _ in 0..i {
-                row = row.iter().map(|n| *n % 9 + 1).collect();
+    let mut risk = Array2::from_elem(dim, u32::MAX);
+    let mut previous = Array2::<u8>::zeros(dim);
+    let mut heap = BinaryHeap::new();
+    risk[start] = 0;
+    heap.push(Reverse((estimate(start), 0, start)));
+
+    while let Some(Reverse((_, score, position))) = heap.pop() {
+        if Some(position) == finish {
+            break;
+        }
+        // skip stale entries superseded by a cheaper route
+        if score > risk[position] {
+            continue;
+        }
+        for (d, pt) in get_adjacents(dim, position) {
+            let sc = score + grid.risk(pt);
+            if sc < risk[pt] {
+                risk[pt] = sc;
+                previous[pt] = d as u8 + 1;
+                heap.push(Reverse((sc + estimate(pt), sc, pt)));
             }
-            grid.push_row(ArrayView::from(&row)).unwrap();
         }
     }
-    grid
+    (risk, previous)
 }
 
-fn get_adjacents(grid: &Array2<u32>, position: (usize, usize)) -> Vec<(usize, usize)> {
-    let (i, j) = (position.0 as i32, position.1 as i32);
-    let range = &0..&(grid.nrows() as i32);
-    [
-        (i, j + 1),
-        (i + 1, j),
-        (i - 1, j),
-        (i, j - 1),
-        //(i - 1, j - 1),
-        //(i - 1, j + 1),
-        //(i + 1, j - 1),
-        //(i + 1, j + 1),
-    ]
-    .iter()
-    .filter(|(r, c)| range.contains(&r) && range.contains(&c))
-    .map(|(r, c)| (*r as usize, *c as usize))
-    .collect::<Vec<(_, _)>>()
-}
-
-fn calc_risk(
-    current: (usize, usize),
-    scored: &HashSet<(usize, usize)>,
-    level: usize,
-    score: u32,
-    best_score: &mut u32,
-    risk: &Array2<u32>,
-) {
-    for pt in get_adjacents(risk, current) {
-        let sc = score + risk[[pt.0, pt.1]];
-        if sc < *best_score {
-            if scored.contains(&pt) {
-                *best_score = sc;
-            } else if level < 4 { // empirically cheating on recursion depth
-                calc_risk(pt, scored, level + 1, sc, best_score, risk);
-            }
-        }
+// follow the directions recorded by `search` back from finish to start
+fn trace_path(previous: &Array2<u8>, start: (usize, usize), finish: (usize, usize)) -> Vec<(usize, usize)> {
+    let mut path = vec![finish];
+    let mut pt = finish;
+    while pt != start {
+        let (dr, dc) = DIRECTIONS[previous[pt] as usize - 1];
+        pt = ((pt.0 as i64 - dr) as usize, (pt.1 as i64 - dc) as usize);
+        path.push(pt);
     }
+    path.reverse();
+    path
 }
 
-fn solution(grid: &Array2<u32>, start: (usize, usize), finish: (usize, usize)) -> u32 {
-    let mut scored = HashSet::<(usize, usize)>::from_iter(vec![start]);
-    let mut risk = grid.clone();
+// the total risk and the cells from start to finish of a minimum risk path
+fn shortest_path<G: RiskMap>(
+    grid: &G,
+    start: (usize, usize),
+    finish: (usize, usize),
+    astar: bool,
+) -> Option<(u32, Vec<(usize, usize)>)> {
+    let (risk, previous) = search(grid, start, Some(finish), astar);
+    match risk[finish] {
+        u32::MAX => None,
+        total => Some((total, trace_path(&previous, start, finish))),
+    }
+}
 
-    for i in 1..grid.nrows() {
-        let mut edge = vec![];
-        for j in 0..i {
-            edge.push((i, j));
-            edge.push((j, i));
+// the risk digits with the path in bold red, or without color only the path digits
+fn render_path<G: RiskMap>(grid: &G, path: &[(usize, usize)], color: bool) -> String {
+    let on_path = path.iter().copied().collect::<HashSet<_>>();
+    let (nrows, ncols) = grid.dim();
+    let mut out = String::new();
+    for r in 0..nrows {
+        for c in 0..ncols {
+            let digit = grid.risk((r, c)).to_string();
+            out += &match (on_path.contains(&(r, c)), color) {
+                (true, true) => format!("\x1b[1;31m{}\x1b[0m", digit),
+                (true, false) | (false, true) => digit,
+                (false, false) => ".".to_string(),
+            };
         }
-        edge.push((i, i));
-
-        for pt in edge {
-            let mut best_score = u32::MAX;
-            let score = risk[[pt.0, pt.1]];
-            calc_risk(pt, &scored, 0, score, &mut best_score, &risk);
-            risk[[pt.0, pt.1]] = best_score;
-            scored.insert(pt);
+        out += "\n";
+    }
+    out
+}
+
+// risk as shades of gray (darker is riskier) with the path in red
+fn write_path_ppm<G: RiskMap>(file: &Path, grid: &G, path: &[(usize, usize)]) -> std::io::Result<()> {
+    let on_path = path.iter().copied().collect::<HashSet<_>>();
+    let (nrows, ncols) = grid.dim();
+    let mut out = BufWriter::new(File::create(file)?);
+    write_ppm(&mut out, nrows, ncols, |r, c| match on_path.contains(&(r, c)) {
+        true => [255, 0, 0],
+        false => {
+            let shade = 255 - (grid.risk((r, c)).min(9) * 25) as u8;
+            [shade, shade, shade]
         }
+    })?;
+    out.flush()
+}
+
+// one line per row of space separated cumulative risks, unreachable cells are "-"
+fn write_risk_matrix(file: &Path, risk: &Array2<u32>) -> std::io::Result<()> {
+    let mut out = BufWriter::new(File::create(file)?);
+    for row in risk.rows() {
+        let line = row
+            .iter()
+            .map(|n| match *n {
+                u32::MAX => "-".to_string(),
+                n => n.to_string(),
+            })
+            .collect::<Vec<_>>();
+        writeln!(out, "{}", line.join(" "))?;
     }
+    out.flush()
+}
+
+fn solution<G: RiskMap>(grid: &G, start: (usize, usize), finish: (usize, usize)) -> u32 {
+    shortest_path(grid, start, finish, false)
+        .expect("finish is unreachable")
+        .0
+}
 
-    //println!("risk =\n{:?}", risk);
-    risk[[finish.0, finish.1]] - risk[[start.0, start.1]]
+// a tiling factor of 0 would leave an empty map with no corner to reach
+fn parse_tiles(s: &str) -> Result<NonZeroUsize, String> {
+    let n = s.parse::<usize>().map_err(|e| e.to_string())?;
+    NonZeroUsize::new(n).ok_or("the tiling factor must be at least 1".to_string())
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -132,6 +236,30 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     struct Cli {
         #[structopt(short, long, parse(from_os_str), help = "file|stdin -- puzzle input")]
         input: Option<std::path::PathBuf>,
+
+        #[structopt(
+            short,
+            long,
+            default_value = "5",
+            parse(try_from_str = parse_tiles),
+            help = "tiling factor of the part 2 map"
+        )]
+        tiles: NonZeroUsize,
+
+        #[structopt(short, long, help = "print the minimum risk path over the grid")]
+        path: bool,
+
+        #[structopt(long, help = "print the path digits only, without color")]
+        plain: bool,
+
+        #[structopt(long, parse(from_os_str), help = "write the path over the grid as a PPM image")]
+        ppm: Option<std::path::PathBuf>,
+
+        #[structopt(long, parse(from_os_str), help = "write the cumulative risk of every cell")]
+        risk_matrix: Option<std::path::PathBuf>,
+
+        #[structopt(long, help = "draw the part 2 tiled map rather than the part 1 grid")]
+        tiled: bool,
     }
     let args = Cli::from_args();
 
@@ -144,11 +272,32 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         solution(&grid, (0, 0), (grid.nrows() - 1, grid.ncols() - 1))
     );
 
-    let grid = get_grid_x5(&data);
-    println!(
-        "Answer Part 2 = {}",
-        solution(&grid, (0, 0), (grid.nrows() - 1, grid.ncols() - 1))
-    );
+    let tiled = TiledGrid {
+        grid: &grid,
+        factor: args.tiles.get(),
+    };
+    let (nrows, ncols) = tiled.dim();
+    println!("Answer Part 2 = {}", solution(&tiled, (0, 0), (nrows - 1, ncols - 1)));
+
+    if args.path || args.ppm.is_some() || args.risk_matrix.is_some() {
+        let map: &dyn RiskMap = match args.tiled {
+            true => &tiled,
+            false => &grid,
+        };
+        let (nrows, ncols) = map.dim();
+        let (risk, previous) = search(&map, (0, 0), None, false);
+        let path = trace_path(&previous, (0, 0), (nrows - 1, ncols - 1));
+
+        if args.path {
+            print!("{}", render_path(&map, &path, !args.plain));
+        }
+        if let Some(file) = args.ppm {
+            write_path_ppm(&file, &map, &path)?;
+        }
+        if let Some(file) = args.risk_matrix {
+            write_risk_matrix(&file, &risk)?;
+        }
+    }
     Ok(())
 }
 
@@ -178,14 +327,78 @@ mod tests {
     #[test]
     fn part2_example() {
         let data = get_data("input-example");
-        let grid = get_grid_x5(&data);
-        assert_eq!(solution(&grid, (0, 0), (grid.nrows() - 1, grid.ncols() - 1)), 315);
+        let grid = get_grid(&data);
+        let tiled = TiledGrid { grid: &grid, factor: 5 };
+        let (nrows, ncols) = tiled.dim();
+        assert_eq!(solution(&tiled, (0, 0), (nrows - 1, ncols - 1)), 315);
     }
 
     #[test]
     fn part2_actual() {
         let data = get_data("input-actual");
-        let grid = get_grid_x5(&data);
-        assert_eq!(solution(&grid, (0, 0), (grid.nrows() - 1, grid.ncols() - 1)), 2879);
+        let grid = get_grid(&data);
+        let tiled = TiledGrid { grid: &grid, factor: 5 };
+        let (nrows, ncols) = tiled.dim();
+        assert_eq!(solution(&tiled, (0, 0), (nrows - 1, ncols - 1)), 2879);
+    }
+
+    #[test]
+    fn winding_path() {
+        let data = ["19111", "19191", "11191", "99991"].map(String::from);
+        let grid = get_grid(&data);
+        for astar in [false, true] {
+            let (risk, path) = shortest_path(&grid, (0, 0), (3, 4), astar).unwrap();
+            assert_eq!(risk, 11);
+            assert_eq!(path.len(), 12);
+            assert_eq!(path[4], (2, 2));
+        }
+
+        let (risk, path) = shortest_path(&grid, (1, 2), (3, 0), false).unwrap();
+        assert_eq!(risk, 12);
+        assert_eq!(path.first(), Some(&(1, 2)));
+        assert_eq!(path.last(), Some(&(3, 0)));
+    }
+
+    #[test]
+    fn astar_actual() {
+        let data = get_data("input-actual");
+        let grid = get_grid(&data);
+        let tiled = TiledGrid { grid: &grid, factor: 5 };
+        let (nrows, ncols) = tiled.dim();
+        assert_eq!(
+            shortest_path(&tiled, (0, 0), (nrows - 1, ncols - 1), true).unwrap().0,
+            2879
+        );
+    }
+
+    #[test]
+    fn tiled_view() {
+        let grid = get_grid(&["8".to_string()]);
+        let tiled = TiledGrid { grid: &grid, factor: 5 };
+        let row = (0..5).map(|c| tiled.risk((0, c))).collect::<Vec<_>>();
+        assert_eq!(row, vec![8, 9, 1, 2, 3]);
+        assert_eq!(tiled.risk((4, 4)), 7);
+
+        let data = get_data("input-example");
+        let grid = get_grid(&data);
+        let tiled = TiledGrid {
+            grid: &grid,
+            factor: 50,
+        };
+        assert_eq!(tiled.dim(), (500, 500));
+        assert_eq!(tiled.risk((499, 499)), (grid[[9, 9]] + 98 - 1) % 9 + 1);
+    }
+
+    #[test]
+    fn path_rendering() {
+        let data = ["19111", "19191", "11191", "99991"].map(String::from);
+        let grid = get_grid(&data);
+        let (_, path) = shortest_path(&grid, (0, 0), (3, 4), false).unwrap();
+        assert_eq!(render_path(&grid, &path, false), "1.111\n1.1.1\n111.1\n....1\n");
+        assert!(render_path(&grid, &path, true).starts_with("\x1b[1;31m1\x1b[0m9"));
+
+        let (risk, _) = search(&grid, (0, 0), None, false);
+        assert_eq!(risk.row(0).to_vec(), vec![0, 9, 6, 7, 8]);
+        assert_eq!(risk[[3, 4]], 11);
     }
 }
@@ -1,4 +1,5 @@
 use general::read_data_lines;
+use num::{BigUint, ToPrimitive};
 use structopt::StructOpt;
 
 const PUZZLE_NAME: &str = "Advent of Code: Day 16 -- Version:";
@@ -6,6 +7,7 @@ const PUZZLE_ABOUT: &str = "Packet Decoder: https://adventofcode.com/2021/day/16
 
 const PACKET_HEADER: usize = 6;
 const MIN_PACKET_BITS: usize = 11; // PACKET_HEADER + 5-bit NUM
+const MAX_DEPTH: usize = 256; // nesting allowed by the recursive decoder and tree walks
 
 // Literal value packets encode a single binary number.
 // To do this, the binary number is padded with leading zeroes until its length is a multiple of four bits,
@@ -76,8 +78,11 @@ const MIN_PACKET_BITS: usize = 11; // PACKET_HEADER + 5-bit NUM
 //   Packets with type ID 7 are equal to packets - their value is 1 if the value of the first sub-packet is equal to the value of
 //   the second sub-packet; otherwise, their value is 0. These packets always have exactly two sub-packets.
 
+// `offset` is the bit position of the packet header within the transmission,
+// operators always carry their sub-packets whichever length type they use, literals have none
 #[derive(Debug, PartialEq)]
 struct Packet {
+    offset: usize,
     version: u8,
     id: TypeId,
     op: Op,
@@ -90,9 +95,9 @@ pub enum TypeId {
     Operator(Payload),
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Op {
-    NUM(u64),
+    NUM(BigUint),
     SUM,
     PROD,
     MIN,
@@ -108,172 +113,361 @@ pub enum Payload {
     SubPacketLen(usize), // number of following packets
 }
 
-fn bits2num(bits: &[u8]) -> u64 {
-    bits.iter().fold(0, |acc, b| acc << 1 | *b as u64)
+// why decoding or evaluating a transmission failed
+#[derive(Debug, PartialEq)]
+pub enum Reason {
+    BadHex(char),
+    Empty,                                      // no packets in the transmission
+    Truncated(&'static str),                    // the transmission ends inside this field
+    Overrun { parent_end: usize },              // a sub-packet runs past the bit length of its parent
+    Operands { op: Op, count: usize },          // wrong number of operands for the operator
+    TooDeep { depth: usize },                   // sub-packets nest deeper than MAX_DEPTH
+    Overflow { op: Op, backend: &'static str }, // the value doesn't fit the numeric backend
+}
+
+// `offset` is the bit where decoding failed, `path` the sub-packet indices leading to the packet being decoded
+#[derive(Debug, PartialEq)]
+pub struct DecodeError {
+    offset: usize,
+    path: Vec<usize>,
+    reason: Reason,
+}
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let path = self.path.iter().map(|i| i.to_string()).collect::<Vec<_>>().join(".");
+        write!(f, "bit {} (packet {}): ", self.offset, path)?;
+        match &self.reason {
+            Reason::BadHex(c) => write!(f, "invalid hex digit {:?}", c),
+            Reason::Empty => write!(f, "no packets in transmission"),
+            Reason::Truncated(field) => write!(f, "transmission ends inside the {}", field),
+            Reason::Overrun { parent_end } => {
+                write!(f, "sub-packet runs past the end of its parent at bit {}", parent_end)
+            }
+            Reason::Operands { op, count } => write!(f, "{:?} can't take {} operand(s)", op, count),
+            Reason::TooDeep { depth } => write!(f, "sub-packets nest deeper than {} levels", depth),
+            Reason::Overflow { op, backend } => write!(f, "{:?} overflows {}", op, backend),
+        }
+    }
+}
+
+// a transmission packed 8 bits to the byte, most significant bit first, `len` counts the bits in use
+#[derive(Debug, Default, PartialEq)]
+struct Bits {
+    bytes: Vec<u8>,
+    len: usize,
+}
+
+impl Bits {
+    fn reader(&self) -> BitReader<'_> {
+        BitReader { bits: self, pos: 0 }
+    }
+
+    // appends the low `width` bits of n
+    fn push(&mut self, n: u64, width: usize) {
+        for i in (0..width).rev() {
+            if self.len.is_multiple_of(8) {
+                self.bytes.push(0);
+            }
+            self.bytes[self.len / 8] |= ((n >> i & 1) as u8) << (7 - self.len % 8);
+            self.len += 1;
+        }
+    }
+
+    fn append(&mut self, other: &Bits) {
+        let mut reader = other.reader();
+        while reader.remaining() > 0 {
+            let width = reader.remaining().min(64);
+            self.push(reader.read(width).unwrap(), width);
+        }
+    }
+
+    // hex digits for whole nibbles, the last one zero padded
+    fn to_hex(&self) -> String {
+        let hex = self.bytes.iter().map(|b| format!("{:02X}", b)).collect::<String>();
+        hex[..self.len.div_ceil(4)].to_string()
+    }
+}
+
+// a cursor reading fields of up to 64 bits
+#[derive(Debug, Clone)]
+struct BitReader<'a> {
+    bits: &'a Bits,
+    pos: usize,
 }
 
-fn decode_packet(bits: &[u8]) -> Packet {
-    let version = bits2num(&bits[0..3]) as u8;
-    let type_id = bits2num(&bits[3..6]) as u8;
+impl BitReader<'_> {
+    fn pos(&self) -> usize {
+        self.pos
+    }
+
+    fn remaining(&self) -> usize {
+        self.bits.len - self.pos
+    }
+
+    // reads the next `width` bits as a number, None past the end of the transmission
+    fn read(&mut self, width: usize) -> Option<u64> {
+        if width > self.remaining() {
+            return None;
+        }
+        let mut n = 0;
+        let mut width = width;
+        while width > 0 {
+            let used = self.pos % 8;
+            let take = (8 - used).min(width);
+            let chunk = self.bits.bytes[self.pos / 8] as u64 >> (8 - used - take) & ((1 << take) - 1);
+            n = n << take | chunk;
+            self.pos += take;
+            width -= take;
+        }
+        Some(n)
+    }
+
+    // true when only zero padding is left
+    fn at_padding(&self) -> bool {
+        let bytes = &self.bits.bytes[self.pos / 8..];
+        bytes.first().is_none_or(|b| b << (self.pos % 8) == 0) && bytes.iter().skip(1).all(|b| *b == 0)
+    }
+}
+
+// the number of operands an operator accepts
+fn check_operands(op: &Op, count: usize) -> Result<(), Reason> {
+    let valid = match op {
+        Op::NUM(_) => count == 0,
+        Op::SUM | Op::PROD => true,
+        Op::MIN | Op::MAX => count >= 1,
+        Op::GT | Op::LT | Op::EQ => count == 2,
+    };
+    match valid {
+        true => Ok(()),
+        false => Err(Reason::Operands { op: op.clone(), count }),
+    }
+}
+
+// decodes the packet at the reader's position along with all of its sub-packets, leaving the reader
+// on the bit following it, `limit` is the end of the enclosing bit length window (or of the transmission)
+// and `path` the sub-packet indices leading to this packet, both used to report where decoding failed
+fn decode_packet(reader: &mut BitReader, limit: usize, path: &mut Vec<usize>) -> Result<Packet, DecodeError> {
+    let fail = |at: usize, reason: Reason, path: &[usize]| DecodeError {
+        offset: at,
+        path: path.to_vec(),
+        reason,
+    };
+    let total = reader.bits.len;
+    let read = |reader: &mut BitReader, width: usize, field: &'static str, path: &[usize]| {
+        let at = reader.pos();
+        match at + width <= limit {
+            true => Ok(reader.read(width).unwrap()),
+            false if limit < total => Err(fail(at, Reason::Overrun { parent_end: limit }, path)),
+            false => Err(fail(at, Reason::Truncated(field), path)),
+        }
+    };
+
+    let offset = reader.pos();
+    // the path holds the top level index plus one per enclosing packet
+    if path.len() - 1 > MAX_DEPTH {
+        return Err(fail(offset, Reason::TooDeep { depth: MAX_DEPTH }, path));
+    }
+    let header = read(reader, PACKET_HEADER, "header", path)? as u8;
+    let (version, type_id) = (header >> 3, header & 0b111);
 
     let opcode = match type_id {
         0 => Op::SUM,
         1 => Op::PROD,
         2 => Op::MIN,
         3 => Op::MAX,
-        4 => Op::NUM(0), // set below
+        4 => Op::NUM(BigUint::default()), // set below
         5 => Op::GT,
         6 => Op::LT,
         7 => Op::EQ,
-        _ => panic!("invalid type_id = {}", type_id),
+        _ => unreachable!("type_id is 3 bits"),
     };
 
-    let (op, id) = match opcode {
+    let (op, id, sub_packets) = match opcode {
         Op::NUM(_) => {
+            // any number of groups, so literals aren't limited to 64 bits
             let mut nibbles = vec![];
-            for (i, bit) in bits.iter().skip(PACKET_HEADER).enumerate() {
-                if i % 5 == 0 {
-                    if *bit == 0 {
-                        // last 4 bits
-                        nibbles.extend(&bits[(i + PACKET_HEADER + 1)..=(i + PACKET_HEADER + 4)]);
-                        break;
-                    }
-                    continue; // skip every 5th bit
+            loop {
+                let last = read(reader, 1, "literal", path)? == 0;
+                nibbles.push(read(reader, 4, "literal", path)? as u8);
+                if last {
+                    break;
                 }
-                nibbles.push(*bit);
             }
             (
-                Op::NUM(bits2num(&nibbles)),
-                TypeId::Literal(nibbles.len() + nibbles.len() / 4),
+                Op::NUM(BigUint::from_radix_be(&nibbles, 16).unwrap()),
+                TypeId::Literal(nibbles.len() * 5),
+                None,
             )
         }
-        _ => match bits[PACKET_HEADER] == 0 {
-            true => (
-                opcode,
-                TypeId::Operator(Payload::BitLen(
-                    bits2num(&bits[(PACKET_HEADER + 1)..=(PACKET_HEADER + 15)]) as usize,
-                )),
-            ),
-            false => (
-                opcode,
-                TypeId::Operator(Payload::SubPacketLen(
-                    bits2num(&bits[(PACKET_HEADER + 1)..=(PACKET_HEADER + 11)]) as usize,
-                )),
-            ),
-        },
-    };
-
-    let sub_packets: Option<Vec<Packet>> = match id {
-        TypeId::Literal(_) => None,
-        TypeId::Operator(Payload::SubPacketLen(_)) => None,
-        TypeId::Operator(Payload::BitLen(n)) => {
-            Some(get_packets(&bits[(PACKET_HEADER + 16)..(PACKET_HEADER + 16 + n)]))
+        _ => {
+            let mut sub_packets = vec![];
+            let payload = match read(reader, 1, "length type", path)? == 0 {
+                true => {
+                    let n = read(reader, 15, "length", path)? as usize;
+                    let end = reader.pos() + n;
+                    if end > limit {
+                        return Err(match limit < total {
+                            true => fail(reader.pos() - 15, Reason::Overrun { parent_end: limit }, path),
+                            false => fail(reader.pos() - 15, Reason::Truncated("sub-packets"), path),
+                        });
+                    }
+                    while reader.pos() < end {
+                        path.push(sub_packets.len());
+                        sub_packets.push(decode_packet(reader, end, path)?);
+                        path.pop();
+                    }
+                    Payload::BitLen(n)
+                }
+                false => {
+                    let n = read(reader, 11, "length", path)? as usize;
+                    for i in 0..n {
+                        path.push(i);
+                        sub_packets.push(decode_packet(reader, limit, path)?);
+                        path.pop();
+                    }
+                    Payload::SubPacketLen(n)
+                }
+            };
+            check_operands(&opcode, sub_packets.len()).map_err(|reason| fail(offset, reason, path))?;
+            (opcode, TypeId::Operator(payload), Some(sub_packets))
         }
     };
 
-    Packet {
+    Ok(Packet {
+        offset,
         version,
         id,
         op,
         sub_packets,
-    }
+    })
 }
 
-fn get_packets(bits: &[u8]) -> Vec<Packet> {
-    let mut offset = 0;
+// decodes the outermost packets of a transmission, ignoring the zero padding at the end
+fn get_packets(bits: &Bits) -> Result<Vec<Packet>, DecodeError> {
+    let mut reader = bits.reader();
     let mut packets = vec![];
-    while offset + MIN_PACKET_BITS <= bits.len() {
-        let packet = decode_packet(&bits[offset..]);
-        offset += match packet.id {
-            TypeId::Literal(n) => PACKET_HEADER + n,
-            TypeId::Operator(Payload::BitLen(n)) => PACKET_HEADER + n + 16,
-            TypeId::Operator(Payload::SubPacketLen(_)) => PACKET_HEADER + 12,
-        };
-        packets.push(packet);
+    while reader.remaining() >= MIN_PACKET_BITS && !reader.at_padding() {
+        packets.push(decode_packet(&mut reader, bits.len, &mut vec![packets.len()])?);
+    }
+    match packets.is_empty() {
+        true => Err(DecodeError {
+            offset: 0,
+            path: vec![],
+            reason: Reason::Empty,
+        }),
+        false => Ok(packets),
+    }
+}
+
+fn get_bits(msg: &str) -> Result<Bits, DecodeError> {
+    let mut bits = Bits::default();
+    for (i, c) in msg.chars().enumerate() {
+        let nibble = c.to_digit(16).ok_or(DecodeError {
+            offset: i * 4,
+            path: vec![],
+            reason: Reason::BadHex(c),
+        })?;
+        bits.push(nibble as u64, 4);
     }
-    packets
+    Ok(bits)
+}
+
+// a numeric backend for evaluation, arithmetic gives None on overflow
+trait Value: Clone + Ord + std::fmt::Display {
+    const NAME: &'static str;
+    fn from_literal(n: &BigUint) -> Option<Self>;
+    fn checked_sum(values: &[Self]) -> Option<Self>;
+    fn checked_product(values: &[Self]) -> Option<Self>;
 }
 
-fn get_bits(msg: &str) -> Vec<u8> {
-    let mut bits = vec![];
-    for c in msg.chars() {
-        let nibble = u8::from_str_radix(&c.to_string(), 16).expect("hex conversion failed");
-        bits.push(nibble >> 3 & 1);
-        bits.push(nibble >> 2 & 1);
-        bits.push(nibble >> 1 & 1);
-        bits.push(nibble & 1);
+impl Value for u64 {
+    const NAME: &'static str = "u64";
+
+    fn from_literal(n: &BigUint) -> Option<Self> {
+        n.to_u64()
+    }
+
+    fn checked_sum(values: &[Self]) -> Option<Self> {
+        values.iter().try_fold(0u64, |acc, n| acc.checked_add(*n))
+    }
+
+    fn checked_product(values: &[Self]) -> Option<Self> {
+        values.iter().try_fold(1u64, |acc, n| acc.checked_mul(*n))
     }
-    bits
 }
 
-fn apply_operator(op: &Op, values: &[u64]) -> u64 {
-    let mut stack = values.to_vec();
+impl Value for u128 {
+    const NAME: &'static str = "u128";
 
+    fn from_literal(n: &BigUint) -> Option<Self> {
+        n.to_u128()
+    }
+
+    fn checked_sum(values: &[Self]) -> Option<Self> {
+        values.iter().try_fold(0u128, |acc, n| acc.checked_add(*n))
+    }
+
+    fn checked_product(values: &[Self]) -> Option<Self> {
+        values.iter().try_fold(1u128, |acc, n| acc.checked_mul(*n))
+    }
+}
+
+impl Value for BigUint {
+    const NAME: &'static str = "big integer";
+
+    fn from_literal(n: &BigUint) -> Option<Self> {
+        Some(n.clone())
+    }
+
+    fn checked_sum(values: &[Self]) -> Option<Self> {
+        Some(values.iter().sum())
+    }
+
+    fn checked_product(values: &[Self]) -> Option<Self> {
+        Some(values.iter().product())
+    }
+}
+
+// operand counts are checked by `check_operands` beforehand, None when the result doesn't fit
+fn apply_operator<V: Value>(op: &Op, values: &[V]) -> Option<V> {
+    let from_bool = |b: bool| V::from_literal(&BigUint::from(b as u8));
     match op {
-        Op::SUM => stack.iter().sum::<u64>(),
-        Op::PROD => stack.iter().product::<u64>(),
-        Op::MIN => *stack.iter().min().expect("min() failed"),
-        Op::MAX => *stack.iter().max().expect("max() failed"),
-        Op::GT => match stack.pop() < stack.pop() {
-            true => 1,
-            false => 0,
-        },
-        Op::LT => match stack.pop() > stack.pop() {
-            true => 1,
-            false => 0,
-        },
-        Op::EQ => match stack.pop() == stack.pop() {
-            true => 1,
-            false => 0,
-        },
-        _ => panic!("invalid opcode = {:?}", op),
-    }
-}
-
-// counts the actual number of packets needed for an input packet range
-// taking into account SubPacketLen packets extend the count
-fn packets_needed(n: usize, packets: &[Packet]) -> usize {
-    let mut target = n;
-    let mut index = 0;
-    while index < target {
-        if let TypeId::Operator(Payload::SubPacketLen(c)) = packets[index].id {
-            target += c;
-        }
-        index += 1;
-    }
-    index
-}
-
-fn eval(packets: &[Packet]) -> Vec<u64> {
-    let mut stack = vec![];
-    let mut index = 0;
-    while index < packets.len() {
-        let opcode = &packets[index].op;
-        match packets[index].id {
-            TypeId::Literal(_) => {
-                stack.push(match opcode {
-                    Op::NUM(n) => *n,
-                    _ => panic!("Literal Op must be type Op::NUM, op = {:?}", opcode),
-                });
-            }
-            TypeId::Operator(Payload::BitLen(_)) => {
-                stack.push(apply_operator(
-                    opcode,
-                    &eval(packets[index].sub_packets.as_ref().expect("corrupt Payload::BitLen")),
-                ));
-            }
-            TypeId::Operator(Payload::SubPacketLen(n)) => {
-                let count = packets_needed(n, &packets[(index + 1)..]);
-                stack.push(apply_operator(
-                    opcode,
-                    &eval(&packets[(index + 1)..(index + 1 + count)]),
-                ));
-                index += count;
-            }
-        };
-        index += 1;
+        Op::NUM(n) => V::from_literal(n),
+        Op::SUM => V::checked_sum(values),
+        Op::PROD => V::checked_product(values),
+        Op::MIN => values.iter().min().cloned(),
+        Op::MAX => values.iter().max().cloned(),
+        Op::GT => from_bool(values[0] > values[1]),
+        Op::LT => from_bool(values[0] < values[1]),
+        Op::EQ => from_bool(values[0] == values[1]),
+    }
+}
+
+// evaluates a packet tree, which needn't have come from the decoder, `path` locates `packet` within the transmission
+fn eval_at<V: Value>(packet: &Packet, path: &mut Vec<usize>) -> Result<V, DecodeError> {
+    let mut values = vec![];
+    for (i, sub_packet) in packet.sub_packets.iter().flatten().enumerate() {
+        path.push(i);
+        values.push(eval_at(sub_packet, path)?);
+        path.pop();
     }
-    stack
+    let fail = |reason: Reason| DecodeError {
+        offset: packet.offset,
+        path: path.clone(),
+        reason,
+    };
+    check_operands(&packet.op, values.len()).map_err(fail)?;
+    apply_operator(&packet.op, &values).ok_or_else(|| {
+        fail(Reason::Overflow {
+            op: packet.op.clone(),
+            backend: V::NAME,
+        })
+    })
+}
+
+fn eval<V: Value>(packet: &Packet) -> Result<V, DecodeError> {
+    eval_at(packet, &mut vec![0])
 }
 
 fn solution1(packets: &[Packet]) -> u64 {
@@ -287,8 +481,229 @@ fn solution1(packets: &[Packet]) -> u64 {
     total
 }
 
-fn solution2(packets: &[Packet]) -> u64 {
-    eval(packets)[0]
+fn solution2(packets: &[Packet]) -> Result<u64, DecodeError> {
+    eval(&packets[0])
+}
+
+impl Op {
+    // operator name in S-expressions
+    fn symbol(&self) -> &'static str {
+        match self {
+            Op::NUM(_) => "num",
+            Op::SUM => "+",
+            Op::PROD => "*",
+            Op::MIN => "min",
+            Op::MAX => "max",
+            Op::GT => ">",
+            Op::LT => "<",
+            Op::EQ => "==",
+        }
+    }
+}
+
+// one line per packet, indented by depth, showing its bit offset, version, type and length mode
+//
+// Example: C200B40A82
+//        0  v6 SUM  count 2
+//       18    v6 NUM  1  (1 group)
+//       29    v2 NUM  2  (1 group)
+fn disassemble(packets: &[Packet]) -> String {
+    fn lines(packet: &Packet, depth: usize, out: &mut String) {
+        let detail = match (&packet.op, &packet.id) {
+            (Op::NUM(n), TypeId::Literal(bits)) => match bits / 5 {
+                1 => format!("{}  (1 group)", n),
+                groups => format!("{}  ({} groups)", n, groups),
+            },
+            (_, TypeId::Operator(Payload::BitLen(n))) => format!("bits {}", n),
+            (_, TypeId::Operator(Payload::SubPacketLen(n))) => format!("count {}", n),
+            (_, TypeId::Literal(_)) => "?".to_string(),
+        };
+        let name = match packet.op {
+            Op::NUM(_) => "NUM".to_string(),
+            ref op => format!("{:?}", op),
+        };
+        *out += &format!(
+            "{:>8}  {}v{} {:<4} {}\n",
+            packet.offset,
+            "  ".repeat(depth),
+            packet.version,
+            name,
+            detail
+        );
+        for sub_packet in packet.sub_packets.iter().flatten() {
+            lines(sub_packet, depth + 1, out);
+        }
+    }
+
+    let mut out = String::new();
+    for packet in packets {
+        lines(packet, 0, &mut out);
+    }
+    out
+}
+
+// the packet as an S-expression such as "(+ 1 (* 2 3))", with `annotate` each operator is followed by its value
+// as in "(+ 1 (* 2 3):6):7", values are exact big integers
+fn to_sexpr(packet: &Packet, annotate: bool) -> Result<String, DecodeError> {
+    fn sexpr(packet: &Packet, annotate: bool) -> (String, BigUint) {
+        let (terms, values): (Vec<_>, Vec<_>) = packet
+            .sub_packets
+            .iter()
+            .flatten()
+            .map(|sub_packet| sexpr(sub_packet, annotate))
+            .unzip();
+        let value = apply_operator(&packet.op, &values).unwrap();
+        let text = match (&packet.op, annotate) {
+            (Op::NUM(n), _) => n.to_string(),
+            (op, false) => format!("({} {})", op.symbol(), terms.join(" ")),
+            (op, true) => format!("({} {}):{}", op.symbol(), terms.join(" "), value),
+        };
+        (text, value)
+    }
+
+    // reports malformed trees before `sexpr` relies on them
+    eval::<BigUint>(packet)?;
+    Ok(sexpr(packet, annotate).0)
+}
+
+// how the encoder records the sub-packets of an operator, `AsPacket` keeps the length type in each packet's id
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum LengthMode {
+    BitLen,
+    SubPacketLen,
+    AsPacket,
+}
+
+// appends the bits of a packet and its sub-packets, lengths are recomputed so only `op`, `version`
+// and the tree shape of `packet` matter (plus the length type with `LengthMode::AsPacket`)
+fn encode_packet(packet: &Packet, mode: LengthMode, bits: &mut Bits) -> Result<(), String> {
+    let type_id = match packet.op {
+        Op::SUM => 0,
+        Op::PROD => 1,
+        Op::MIN => 2,
+        Op::MAX => 3,
+        Op::NUM(_) => 4,
+        Op::GT => 5,
+        Op::LT => 6,
+        Op::EQ => 7,
+    };
+    if packet.version > 7 {
+        return Err(format!("version {} doesn't fit in 3 bits", packet.version));
+    }
+    bits.push(packet.version as u64, 3);
+    bits.push(type_id, 3);
+
+    let sub_packets = packet.sub_packets.as_deref().unwrap_or_default();
+    check_operands(&packet.op, sub_packets.len())
+        .map_err(|_| format!("{:?} can't take {} operand(s)", packet.op, sub_packets.len()))?;
+
+    match &packet.op {
+        // groups of 4 bits, each prefixed by 1 except the last which is prefixed by 0
+        Op::NUM(n) => {
+            let nibbles = n.to_radix_be(16);
+            for (i, nibble) in nibbles.iter().enumerate() {
+                bits.push((i + 1 < nibbles.len()) as u64, 1);
+                bits.push(*nibble as u64, 4);
+            }
+        }
+        _ => {
+            let bit_len = match (mode, &packet.id) {
+                (LengthMode::BitLen, _) => true,
+                (LengthMode::SubPacketLen, _) => false,
+                (LengthMode::AsPacket, id) => matches!(id, TypeId::Operator(Payload::BitLen(_))),
+            };
+            let mut payload = Bits::default();
+            for sub_packet in sub_packets {
+                encode_packet(sub_packet, mode, &mut payload)?;
+            }
+            match bit_len {
+                true if payload.len < 1 << 15 => {
+                    bits.push(0, 1);
+                    bits.push(payload.len as u64, 15);
+                }
+                false if sub_packets.len() < 1 << 11 => {
+                    bits.push(1, 1);
+                    bits.push(sub_packets.len() as u64, 11);
+                }
+                true => return Err(format!("{} bits of sub-packets don't fit in 15 bits", payload.len)),
+                false => return Err(format!("{} sub-packets don't fit in 11 bits", sub_packets.len())),
+            }
+            bits.append(&payload);
+        }
+    }
+    Ok(())
+}
+
+// the hex transmission of a packet, zero padded to a whole number of hex digits
+fn encode(packet: &Packet, mode: LengthMode) -> Result<String, String> {
+    let mut bits = Bits::default();
+    encode_packet(packet, mode, &mut bits)?;
+    Ok(bits.to_hex())
+}
+
+// parses an expression such as "sum(1, prod(2, 3), gt(4, 5))" into a packet tree of version 0 packets
+// offsets are left at 0 and operators count their sub-packets, decode the encoding for the real layout
+fn parse_expression(expr: &str) -> Result<Packet, String> {
+    let tokens = expr
+        .split_inclusive(['(', ')', ','])
+        .flat_map(|t| {
+            let (word, delim) = t.split_at(t.len() - t.ends_with(['(', ')', ',']) as usize);
+            [word.trim(), delim]
+        })
+        .filter(|t| !t.is_empty())
+        .collect::<Vec<_>>();
+
+    fn parse(tokens: &[&str], pos: &mut usize) -> Result<Packet, String> {
+        let token = *tokens.get(*pos).ok_or("unexpected end of expression")?;
+        *pos += 1;
+        if let Ok(n) = token.parse::<BigUint>() {
+            return Ok(Packet {
+                offset: 0,
+                version: 0,
+                id: TypeId::Literal(n.to_radix_be(16).len() * 5),
+                op: Op::NUM(n),
+                sub_packets: None,
+            });
+        }
+        let op = match token.to_lowercase().as_str() {
+            "sum" => Op::SUM,
+            "prod" => Op::PROD,
+            "min" => Op::MIN,
+            "max" => Op::MAX,
+            "gt" => Op::GT,
+            "lt" => Op::LT,
+            "eq" => Op::EQ,
+            _ => return Err(format!("unknown operator or number {:?}", token)),
+        };
+        if tokens.get(*pos) != Some(&"(") {
+            return Err(format!("expected '(' after {:?}", token));
+        }
+        *pos += 1;
+        let mut sub_packets = vec![];
+        while tokens.get(*pos) != Some(&")") {
+            sub_packets.push(parse(tokens, pos)?);
+            match tokens.get(*pos) {
+                Some(&",") => *pos += 1,
+                Some(&")") => (),
+                _ => return Err(format!("expected ',' or ')' in the operands of {:?}", token)),
+            }
+        }
+        *pos += 1;
+        Ok(Packet {
+            offset: 0,
+            version: 0,
+            id: TypeId::Operator(Payload::SubPacketLen(sub_packets.len())),
+            op,
+            sub_packets: Some(sub_packets),
+        })
+    }
+
+    let mut pos = 0;
+    let packet = parse(&tokens, &mut pos)?;
+    match tokens.get(pos) {
+        None => Ok(packet),
+        Some(token) => Err(format!("unexpected {:?} after the expression", token)),
+    }
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -297,17 +712,94 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     struct Cli {
         #[structopt(short, long, parse(from_os_str), help = "file|stdin -- puzzle input")]
         input: Option<std::path::PathBuf>,
+
+        #[structopt(
+            short,
+            long,
+            help = "print each packet with its bit offset, version, type and length mode"
+        )]
+        disasm: bool,
+
+        #[structopt(
+            long,
+            help = "print the transmission as an S-expression with the value of each operator"
+        )]
+        sexpr: bool,
+
+        #[structopt(
+            short,
+            long,
+            help = "print the transmission of an expression such as \"sum(1, prod(2, 3))\""
+        )]
+        encode: Option<String>,
+
+        #[structopt(
+            short,
+            long,
+            default_value = "u64",
+            possible_values = &["u64", "u128", "big"],
+            help = "numeric backend for part 2, u64 and u128 report overflow"
+        )]
+        backend: String,
+
+        #[structopt(
+            long,
+            help = "print the input transmission encoded again, showing the lengths the encoder picks"
+        )]
+        reencode: bool,
+
+        #[structopt(long, help = "encode operators with the bit length of their sub-packets")]
+        bit_len: bool,
+
+        #[structopt(
+            long,
+            conflicts_with = "bit-len",
+            help = "encode operators with their count of sub-packets"
+        )]
+        count: bool,
     }
     let args = Cli::from_args();
 
     // ==============================================================
 
+    // parsed expressions count their sub-packets unless told otherwise
+    let mode = match (args.bit_len, args.count) {
+        (true, _) => LengthMode::BitLen,
+        (_, true) => LengthMode::SubPacketLen,
+        _ => LengthMode::AsPacket,
+    };
+    if let Some(expr) = args.encode {
+        println!("{}", encode(&parse_expression(&expr)?, mode)?);
+        return Ok(());
+    }
+
     let data = read_data_lines::<String>(args.input)?;
-    let bits = get_bits(&data[0]);
-    let packets = get_packets(&bits);
-    //println!("packets = {:#?}", packets);
+    let bits = get_bits(&data[0]).map_err(|e| e.to_string())?;
+    let packets = get_packets(&bits).map_err(|e| e.to_string())?;
+
+    // inspect the transmission before evaluating it, so it's shown even when evaluation fails
+    if args.disasm {
+        print!("{}", disassemble(&packets));
+    }
+    if args.sexpr {
+        for packet in &packets {
+            println!("{}", to_sexpr(packet, true).map_err(|e| e.to_string())?);
+        }
+    }
+
     println!("Answer Part 1 = {:?}", solution1(&packets));
-    println!("Answer Part 2 = {:?}", solution2(&packets));
+    let part2 = match args.backend.as_str() {
+        "u128" => eval::<u128>(&packets[0]).map(|n| n.to_string()),
+        "big" => eval::<BigUint>(&packets[0]).map(|n| n.to_string()),
+        _ => solution2(&packets).map(|n| n.to_string()),
+    };
+    println!("Answer Part 2 = {}", part2.map_err(|e| e.to_string())?);
+
+    if args.reencode {
+        for packet in &packets {
+            println!("{}", encode(packet, mode)?);
+        }
+    }
 
     Ok(())
 }
@@ -323,66 +815,67 @@ mod tests {
     #[test]
     fn test1() {
         let data = "C200B40A82";
-        let bits = get_bits(data);
-        let packets = get_packets(&bits);
+        let bits = get_bits(data).unwrap();
+        let packets = get_packets(&bits).unwrap();
 
-        assert_eq!(packets.len(), 3);
+        assert_eq!(packets.len(), 1);
         assert_eq!(
             packets[0],
             Packet {
+                offset: 0,
                 version: 6,
                 id: TypeId::Operator(Payload::SubPacketLen(2)),
                 op: Op::SUM,
-                sub_packets: None
-            }
-        );
-        assert_eq!(
-            packets[1],
-            Packet {
-                version: 6,
-                id: TypeId::Literal(5),
-                op: Op::NUM(1),
-                sub_packets: None
-            }
-        );
-        assert_eq!(
-            packets[2],
-            Packet {
-                version: 2,
-                id: TypeId::Literal(5),
-                op: Op::NUM(2),
-                sub_packets: None
+                sub_packets: Some(vec![
+                    Packet {
+                        offset: 18,
+                        version: 6,
+                        id: TypeId::Literal(5),
+                        op: Op::NUM(BigUint::from(1u32)),
+                        sub_packets: None
+                    },
+                    Packet {
+                        offset: 29,
+                        version: 2,
+                        id: TypeId::Literal(5),
+                        op: Op::NUM(BigUint::from(2u32)),
+                        sub_packets: None
+                    },
+                ])
             }
         );
 
         assert_eq!(solution1(&packets), 6 + 6 + 2);
-        assert_eq!(solution2(&packets), 1 + 2);
+        assert_eq!(solution2(&packets).unwrap(), 1 + 2);
     }
 
     #[test]
     fn test2() {
         let data = "04005AC33890";
-        let bits = get_bits(data);
-        let packets = get_packets(&bits);
+        let bits = get_bits(data).unwrap();
+        let packets = get_packets(&bits).unwrap();
 
         assert_eq!(packets.len(), 1);
         assert_eq!(
             packets[0],
             Packet {
+                offset: 0,
                 version: 0,
                 id: TypeId::Operator(Payload::BitLen(22)),
                 op: Op::PROD,
                 sub_packets: Some(vec![
                     Packet {
+                        offset: 22,
                         version: 5,
                         id: TypeId::Literal(5),
-                        op: Op::NUM(6),
+                        op: Op::NUM(BigUint::from(6u32)),
                         sub_packets: None
                     },
                     Packet {
+                        offset: 33,
                         version: 3,
                         id: TypeId::Literal(5),
-                        op: Op::NUM(9),
+                        op: Op::NUM(BigUint::from(9u32)),
                         sub_packets: None
                     },
                 ])
@@ -390,131 +883,147 @@ mod tests {
         );
 
         assert_eq!(solution1(&packets), 5 + 3);
-        assert_eq!(solution2(&packets), 6 * 9);
+        assert_eq!(solution2(&packets).unwrap(), 6 * 9);
     }
 
     #[test]
     fn test3() {
         let data = "9C0141080250320F1802104A08";
-        let bits = get_bits(data);
-        let packets = get_packets(&bits);
+        let bits = get_bits(data).unwrap();
+        let packets = get_packets(&bits).unwrap();
 
         assert_eq!(packets.len(), 1);
         assert_eq!(
             packets[0],
             Packet {
+                offset: 0,
                 version: 4,
                 id: TypeId::Operator(Payload::BitLen(80)),
                 op: Op::EQ,
                 sub_packets: Some(vec![
                     Packet {
+                        offset: 22,
                         version: 2,
                         id: TypeId::Operator(Payload::SubPacketLen(2)),
                         op: Op::SUM,
-                        sub_packets: None,
-                    },
-                    Packet {
-                        version: 2,
-                        id: TypeId::Literal(5),
-                        op: Op::NUM(1),
-                        sub_packets: None
-                    },
-                    Packet {
-                        version: 4,
-                        id: TypeId::Literal(5),
-                        op: Op::NUM(3),
-                        sub_packets: None
+                        sub_packets: Some(vec![
+                            Packet {
+                                offset: 40,
+                                version: 2,
+                                id: TypeId::Literal(5),
+                                op: Op::NUM(BigUint::from(1u32)),
+                                sub_packets: None
+                            },
+                            Packet {
+                                offset: 51,
+                                version: 4,
+                                id: TypeId::Literal(5),
+                                op: Op::NUM(BigUint::from(3u32)),
+                                sub_packets: None
+                            },
+                        ]),
                     },
                     Packet {
+                        offset: 62,
                         version: 6,
                         id: TypeId::Operator(Payload::SubPacketLen(2)),
                         op: Op::PROD,
-                        sub_packets: None,
-                    },
-                    Packet {
-                        version: 0,
-                        id: TypeId::Literal(5),
-                        op: Op::NUM(2),
-                        sub_packets: None
-                    },
-                    Packet {
-                        version: 2,
-                        id: TypeId::Literal(5),
-                        op: Op::NUM(2),
-                        sub_packets: None
+                        sub_packets: Some(vec![
+                            Packet {
+                                offset: 80,
+                                version: 0,
+                                id: TypeId::Literal(5),
+                                op: Op::NUM(BigUint::from(2u32)),
+                                sub_packets: None
+                            },
+                            Packet {
+                                offset: 91,
+                                version: 2,
+                                id: TypeId::Literal(5),
+                                op: Op::NUM(BigUint::from(2u32)),
+                                sub_packets: None
+                            },
+                        ]),
                     },
                 ])
             }
         );
 
-        assert_eq!(solution1(&packets), 4 + 2 + 2 + 4 + 6 + 0 + 2);
-        assert_eq!(solution2(&packets), 1);
+        assert_eq!(solution1(&packets), [4, 2, 2, 4, 6, 0, 2].iter().sum::<u64>());
+        assert_eq!(solution2(&packets).unwrap(), 1);
     }
 
     #[test]
     fn test4() {
         let data = "880086C3E88112";
-        let bits = get_bits(data);
-        let packets = get_packets(&bits);
+        let bits = get_bits(data).unwrap();
+        let packets = get_packets(&bits).unwrap();
 
         assert_eq!(packets.len(), 1);
         assert_eq!(
             packets[0],
             Packet {
+                offset: 0,
                 version: 4,
                 id: TypeId::Operator(Payload::BitLen(33)),
                 op: Op::MIN,
                 sub_packets: Some(vec![
                     Packet {
+                        offset: 22,
                         version: 5,
                         id: TypeId::Literal(5),
-                        op: Op::NUM(7),
+                        op: Op::NUM(BigUint::from(7u32)),
                         sub_packets: None
                     },
                     Packet {
+                        offset: 33,
                         version: 6,
                         id: TypeId::Literal(5),
-                        op: Op::NUM(8),
+                        op: Op::NUM(BigUint::from(8u32)),
                         sub_packets: None
                     },
                     Packet {
+                        offset: 44,
                         version: 0,
                         id: TypeId::Literal(5),
-                        op: Op::NUM(9),
+                        op: Op::NUM(BigUint::from(9u32)),
                         sub_packets: None
                     },
                 ])
             }
         );
 
-        assert_eq!(solution1(&packets), 4 + 5 + 6 + 0);
-        assert_eq!(solution2(&packets), *[7, 8, 9].iter().min().unwrap());
+        assert_eq!(solution1(&packets), [4, 5, 6, 0].iter().sum::<u64>());
+        assert_eq!(solution2(&packets).unwrap(), *[7, 8, 9].iter().min().unwrap());
     }
 
     #[test]
     fn test5() {
         let data = "38006F45291200";
-        let bits = get_bits(data);
-        let packets = get_packets(&bits);
+        let bits = get_bits(data).unwrap();
+        let packets = get_packets(&bits).unwrap();
 
         assert_eq!(packets.len(), 1);
         assert_eq!(
             packets[0],
             Packet {
+                offset: 0,
                 version: 1,
                 id: TypeId::Operator(Payload::BitLen(27)),
                 op: Op::LT,
                 sub_packets: Some(vec![
                     Packet {
+                        offset: 22,
                         version: 6,
                         id: TypeId::Literal(5),
-                        op: Op::NUM(10),
+                        op: Op::NUM(BigUint::from(10u32)),
                         sub_packets: None
                     },
                     Packet {
+                        offset: 33,
                         version: 2,
                         id: TypeId::Literal(10),
-                        op: Op::NUM(20),
+                        op: Op::NUM(BigUint::from(20u32)),
                         sub_packets: None
                     },
                 ])
@@ -522,38 +1031,227 @@ mod tests {
         );
 
         assert_eq!(solution1(&packets), 1 + 6 + 2);
-        assert_eq!(solution2(&packets), 1);
+        assert_eq!(solution2(&packets).unwrap(), 1);
     }
 
     #[test]
     fn part1_example() {
         let data = get_data("input-example");
-        let bits = get_bits(&data[0]);
-        let packets = get_packets(&bits);
+        let bits = get_bits(&data[0]).unwrap();
+        let packets = get_packets(&bits).unwrap();
         assert_eq!(solution1(&packets), 6);
     }
 
     #[test]
     fn part1_actual() {
         let data = get_data("input-actual");
-        let bits = get_bits(&data[0]);
-        let packets = get_packets(&bits);
+        let bits = get_bits(&data[0]).unwrap();
+        let packets = get_packets(&bits).unwrap();
         assert_eq!(solution1(&packets), 866);
     }
 
     #[test]
     fn part2_example() {
         let data = get_data("input-example");
-        let bits = get_bits(&data[0]);
-        let packets = get_packets(&bits);
-        assert_eq!(solution2(&packets), 2021);
+        let bits = get_bits(&data[0]).unwrap();
+        let packets = get_packets(&bits).unwrap();
+        assert_eq!(solution2(&packets).unwrap(), 2021);
     }
 
     #[test]
     fn part2_actual() {
         let data = get_data("input-actual");
-        let bits = get_bits(&data[0]);
-        let packets = get_packets(&bits);
-        assert_eq!(solution2(&packets), 1392637195518);
+        let bits = get_bits(&data[0]).unwrap();
+        let packets = get_packets(&bits).unwrap();
+        assert_eq!(solution2(&packets).unwrap(), 1392637195518);
+    }
+
+    #[test]
+    fn decode_errors() {
+        let error = |hex: &str| get_bits(hex).and_then(|bits| get_packets(&bits)).unwrap_err();
+
+        assert_eq!(error("C2G0").reason, Reason::BadHex('G'));
+        assert_eq!(error("0000").reason, Reason::Empty);
+
+        // D2FE28 cut before its last literal group
+        let e = error("D2FE");
+        assert_eq!(e.to_string(), "bit 16 (packet 0): transmission ends inside the literal");
+        assert_eq!(
+            (e.offset, e.path, e.reason),
+            (16, vec![0], Reason::Truncated("literal"))
+        );
+
+        // 38006F45291200 with a bit length of 26, one short of its second literal
+        let e = error("38006B45291200");
+        assert_eq!(
+            (e.offset, e.path, e.reason),
+            (45, vec![0, 1], Reason::Overrun { parent_end: 48 })
+        );
+
+        // 880086C3E88112 as GT rather than MIN over its three literals
+        let e = error("940086C3E88112");
+        assert_eq!(
+            (e.offset, e.path, e.reason),
+            (0, vec![0], Reason::Operands { op: Op::GT, count: 3 })
+        );
+    }
+
+    #[test]
+    fn eval_errors() {
+        let packet = Packet {
+            offset: 0,
+            version: 0,
+            id: TypeId::Operator(Payload::SubPacketLen(0)),
+            op: Op::MIN,
+            sub_packets: Some(vec![]),
+        };
+        let e = eval::<u64>(&packet).unwrap_err();
+        assert_eq!(e.reason, Reason::Operands { op: Op::MIN, count: 0 });
+    }
+
+    #[test]
+    fn encode_round_trip() {
+        let examples = [
+            "D2FE28",
+            "C200B40A82",
+            "04005AC33890",
+            "9C0141080250320F1802104A08",
+            "38006F45291200",
+        ];
+        for hex in examples {
+            let packets = get_packets(&get_bits(hex).unwrap()).unwrap();
+            let encoded = encode(&packets[0], LengthMode::AsPacket).unwrap();
+            assert!(hex.starts_with(&encoded));
+            assert_eq!(get_packets(&get_bits(&encoded).unwrap()).unwrap(), packets);
+        }
+
+        let actual = get_data("input-actual");
+        let packets = get_packets(&get_bits(&actual[0]).unwrap()).unwrap();
+        for mode in [LengthMode::BitLen, LengthMode::SubPacketLen] {
+            let encoded = encode(&packets[0], mode).unwrap();
+            let decoded = get_packets(&get_bits(&encoded).unwrap()).unwrap();
+            assert_eq!(solution1(&decoded), 866);
+            assert_eq!(solution2(&decoded).unwrap(), 1392637195518);
+        }
+    }
+
+    #[test]
+    fn encode_expression() {
+        let packet = parse_expression("sum(1, prod(2, 3), gt(4, 5))").unwrap();
+        assert_eq!(eval::<u64>(&packet).unwrap(), 7);
+        assert_eq!(
+            encode(&parse_expression("2021").unwrap(), LengthMode::BitLen).unwrap(),
+            "12FE28"
+        );
+
+        for mode in [LengthMode::BitLen, LengthMode::SubPacketLen] {
+            let hex = encode(&packet, mode).unwrap();
+            let packets = get_packets(&get_bits(&hex).unwrap()).unwrap();
+            assert_eq!(solution2(&packets).unwrap(), 7);
+        }
+
+        assert!(parse_expression("sum(1, 2").is_err());
+        assert!(parse_expression("avg(1, 2)").is_err());
+        assert!(encode(&parse_expression("gt(1)").unwrap(), LengthMode::BitLen).is_err());
+    }
+
+    #[test]
+    fn bit_reader() {
+        let bits = get_bits("D2FE28").unwrap();
+        assert_eq!((bits.bytes.len(), bits.len), (3, 24));
+
+        let mut reader = bits.reader();
+        assert_eq!(reader.read(3), Some(6));
+        assert_eq!(reader.read(3), Some(4));
+        assert_eq!(reader.read(15), Some(0b10111_11110_00101));
+        assert!(reader.at_padding());
+        assert_eq!(reader.read(4), None);
+        assert_eq!(reader.read(3), Some(0));
+        assert_eq!(reader.remaining(), 0);
+
+        let bits = get_bits("0123456789ABCDEF0").unwrap();
+        let mut reader = bits.reader();
+        assert_eq!(reader.read(4), Some(0));
+        assert_eq!(reader.read(64), Some(0x123456789ABCDEF0));
+        assert_eq!(bits.to_hex(), "0123456789ABCDEF0");
+    }
+
+    #[test]
+    fn numeric_backends() {
+        // a literal of 18 nibbles
+        let big = "295147905179352825856"; // 2^68
+        let packet = parse_expression(&format!("sum({}, 1)", big)).unwrap();
+        let hex = encode(&packet, LengthMode::BitLen).unwrap();
+        let packets = get_packets(&get_bits(&hex).unwrap()).unwrap();
+        assert_eq!(packets[0].sub_packets.as_ref().unwrap()[0].id, TypeId::Literal(18 * 5));
+        assert_eq!(eval::<u128>(&packets[0]).unwrap(), 295147905179352825857);
+        let e = eval::<u64>(&packets[0]).unwrap_err();
+        assert_eq!((e.offset, e.path.clone()), (22, vec![0, 0]));
+        assert_eq!(
+            e.to_string(),
+            format!("bit 22 (packet 0.0): NUM({}) overflows u64", big)
+        );
+
+        let product = parse_expression(&format!("prod({}, {}, 16)", u64::MAX, u64::MAX)).unwrap();
+        let e = eval::<u64>(&product).unwrap_err();
+        assert_eq!(
+            e.reason,
+            Reason::Overflow {
+                op: Op::PROD,
+                backend: "u64"
+            }
+        );
+        assert!(eval::<u128>(&product).is_err());
+        assert_eq!(
+            eval::<BigUint>(&product).unwrap(),
+            BigUint::from(u64::MAX) * BigUint::from(u64::MAX) * BigUint::from(16u32)
+        );
+
+        let packets = get_packets(&get_bits(&get_data("input-actual")[0]).unwrap()).unwrap();
+        assert_eq!(eval::<BigUint>(&packets[0]).unwrap(), BigUint::from(1392637195518u64));
+    }
+
+    #[test]
+    fn disassembly() {
+        let packets = get_packets(&get_bits("C200B40A82").unwrap()).unwrap();
+        assert_eq!(
+            disassemble(&packets),
+            "       0  v6 SUM  count 2\n      18    v6 NUM  1  (1 group)\n      29    v2 NUM  2  (1 group)\n"
+        );
+
+        let packets = get_packets(&get_bits("9C0141080250320F1802104A08").unwrap()).unwrap();
+        let lines = disassemble(&packets);
+        assert_eq!(lines.lines().nth(3), Some("      51      v4 NUM  3  (1 group)"));
+        assert_eq!(lines.lines().nth(4), Some("      62    v6 PROD count 2"));
+        assert_eq!(to_sexpr(&packets[0], false).unwrap(), "(== (+ 1 3) (* 2 2))");
+        assert_eq!(to_sexpr(&packets[0], true).unwrap(), "(== (+ 1 3):4 (* 2 2):4):1");
+
+        let packet = parse_expression("sum(1, prod(2, 3), gt(4, 5))").unwrap();
+        assert_eq!(to_sexpr(&packet, true).unwrap(), "(+ 1 (* 2 3):6 (> 4 5):0):7");
+        assert!(to_sexpr(&parse_expression("min()").unwrap(), false).is_err());
+    }
+
+    #[test]
+    fn deep_nesting() {
+        // SUM packets counting one sub-packet each, around a single literal
+        let nested = |depth: usize| {
+            let mut bits = Bits::default();
+            for _ in 0..depth {
+                bits.push(0, PACKET_HEADER); // version 0 SUM
+                bits.push(1, 1);
+                bits.push(1, 11);
+            }
+            bits.push(4, PACKET_HEADER); // version 0 literal 7
+            bits.push(7, 5);
+            get_bits(&bits.to_hex()).unwrap()
+        };
+
+        let packets = get_packets(&nested(MAX_DEPTH)).unwrap();
+        assert_eq!(solution2(&packets).unwrap(), 7);
+        assert_eq!(disassemble(&packets).lines().count(), MAX_DEPTH + 1);
+
+        let e = get_packets(&nested(5000)).unwrap_err();
+        assert_eq!(e.reason, Reason::TooDeep { depth: MAX_DEPTH });
+        assert_eq!((e.offset, e.path.len()), (18 * (MAX_DEPTH + 1), MAX_DEPTH + 2));
     }
 }
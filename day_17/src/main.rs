@@ -1,59 +1,281 @@
+use general::frames::{write_ppm, write_text};
 use general::read_data_lines;
 use regex::Regex;
 use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
 use structopt::StructOpt;
 
 const PUZZLE_NAME: &str = "Advent of Code: Day 17 -- Version:";
 const PUZZLE_ABOUT: &str = "Trick Shot: https://adventofcode.com/2021/day/17";
 
-fn get_target_area(data: &str) -> (i64, i64, i64, i64) {
-    let re = Regex::new(r"target\s+area:\s+x=(\d+)\.\.(\d+),\s+y=(-\d+)\.\.(-\d+)").unwrap();
-    let captures = re.captures(data).unwrap();
-    (
-        captures.get(1).map(|s| s.as_str().parse::<i64>().unwrap()).unwrap(),
-        captures.get(2).map(|s| s.as_str().parse::<i64>().unwrap()).unwrap(),
-        captures.get(3).map(|s| s.as_str().parse::<i64>().unwrap()).unwrap(),
-        captures.get(4).map(|s| s.as_str().parse::<i64>().unwrap()).unwrap(),
-    )
-}
-
-fn solutions(data: &str) -> (i64, usize) {
-    let (xmin, xmax, ymin, ymax) = get_target_area(data);
-
-    let mut best_y = i64::MIN;
-    let mut velocity = HashSet::new();
-
-    for n in ((2.0 * xmin as f64).sqrt().round() as i64)..=xmax {
-        for m in ymin..ymin.abs() {
-            let (mut x, mut y) = (0, 0);
-            let (mut xv, mut yv) = (n as i64, m as i64);
-            let mut max_y = 0;
-            let mut success = false;
-            for _step in 0..(2 * ymin.abs()) {
-                if x + xv == x && (x < xmin || x > xmax) {
-                    break;
-                }
+// the probe launches from (0, 0), the target may lie in any quadrant
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Target {
+    xmin: i64,
+    xmax: i64,
+    ymin: i64,
+    ymax: i64,
+}
 
-                x += xv;
-                y += yv;
-                xv = match xv < 0 {
-                    true => xv + 1,
-                    false => 0.max(xv - 1),
-                };
-                yv -= 1;
-
-                if x >= xmin && x <= xmax && y >= ymin && y <= ymax {
-                    success = true;
-                    velocity.insert((n, m));
-                }
-                max_y = max_y.max(y);
+impl Target {
+    fn contains_x(&self, x: i64) -> bool {
+        (self.xmin..=self.xmax).contains(&x)
+    }
+
+    fn contains_y(&self, y: i64) -> bool {
+        (self.ymin..=self.ymax).contains(&y)
+    }
+}
+
+fn get_target_area(data: &str) -> Result<Target, String> {
+    let re = Regex::new(r"target\s+area:\s+x=(-?\d+)\.\.(-?\d+),\s+y=(-?\d+)\.\.(-?\d+)").unwrap();
+    let captures = re
+        .captures(data)
+        .ok_or(format!("expected \"target area: x=A..B, y=C..D\", got {:?}", data))?;
+    let value = |i: usize| captures[i].parse::<i64>().map_err(|e| e.to_string());
+    let (x1, x2, y1, y2) = (value(1)?, value(2)?, value(3)?, value(4)?);
+    Ok(Target {
+        xmin: x1.min(x2),
+        xmax: x1.max(x2),
+        ymin: y1.min(y2),
+        ymax: y1.max(y2),
+    })
+}
+
+// adds steps lo..=hi to a sorted list of inclusive step ranges, joining it to the last range when adjacent
+fn add_steps(steps: &mut Vec<(u64, u64)>, lo: u64, hi: u64) {
+    match steps.last_mut() {
+        Some(last) if last.1 + 1 == lo => last.1 = hi,
+        _ => steps.push((lo, hi)),
+    }
+}
+
+// the steps where a horizontal velocity puts the probe within the target's x range,
+// drag stops the probe after |vx| steps so the last range is open ended (u64::MAX) when it stops inside
+fn x_steps(vx: i64, target: &Target) -> Vec<(u64, u64)> {
+    let (mut x, mut v) = (0, vx);
+    let mut steps = vec![];
+    for t in 1.. {
+        if v == 0 {
+            if target.contains_x(x) {
+                add_steps(&mut steps, t, u64::MAX);
             }
-            if success && max_y > best_y {
-                best_y = max_y;
+            break;
+        }
+        x += v;
+        v -= v.signum();
+        if target.contains_x(x) {
+            add_steps(&mut steps, t, t);
+        }
+    }
+    steps
+}
+
+// the steps where a vertical velocity puts the probe within the target's y range,
+// a target above the launcher can be crossed twice, once rising and once falling
+fn y_steps(vy: i64, target: &Target) -> Vec<(u64, u64)> {
+    let (mut y, mut v) = (0, vy);
+    let mut steps = vec![];
+    for t in 1.. {
+        y += v;
+        v -= 1;
+        if target.contains_y(y) {
+            add_steps(&mut steps, t, t);
+        }
+        // falling and already below the target
+        if y < target.ymin && v < 0 {
+            break;
+        }
+    }
+    steps
+}
+
+fn overlaps(a: &[(u64, u64)], b: &[(u64, u64)]) -> bool {
+    a.iter().any(|(lo, hi)| b.iter().any(|(c, d)| lo <= d && c <= hi))
+}
+
+// every initial velocity that leaves the probe within the target after some step,
+// each axis is worked out on its own and a velocity is valid when the two share a step
+//
+// any faster horizontally and the first step jumps past the target, drag only slows the probe toward x = 0
+// any faster downwards and the first step falls below the target, any faster upwards and the probe passes
+// the target on the way up (when above) or falls from y = 0 straight past it (when below)
+// a target spanning y = 0 catches every upward launch on its way back down, so there it's the last step
+// the x range can be hit that limits the launch, and if the probe can come to rest inside there's no limit
+fn velocities(target: &Target) -> Result<HashSet<(i64, i64)>, String> {
+    let vx_range = target.xmin.min(0)..=target.xmax.max(0);
+    let xs = vx_range
+        .map(|vx| (vx, x_steps(vx, target)))
+        .filter(|(_, steps)| !steps.is_empty())
+        .collect::<Vec<_>>();
+
+    let mut vy_max = target.ymin.abs().max(target.ymax.abs());
+    if target.contains_y(0) {
+        let last = xs.iter().filter_map(|(_, steps)| steps.last()).map(|(_, hi)| *hi).max();
+        match last {
+            Some(u64::MAX) => return Err(format!("{:?} is hit by infinitely many velocities", target)),
+            Some(last) => vy_max = vy_max.max(last as i64),
+            None => (),
+        }
+    }
+
+    let vy_range = target.ymin.min(0)..=vy_max;
+    let ys = vy_range
+        .map(|vy| (vy, y_steps(vy, target)))
+        .filter(|(_, steps)| !steps.is_empty())
+        .collect::<Vec<_>>();
+
+    let mut valid = HashSet::new();
+    for (vx, x) in &xs {
+        for (vy, y) in &ys {
+            if overlaps(x, y) {
+                valid.insert((*vx, *vy));
             }
         }
     }
-    (best_y, velocity.len())
+    Ok(valid)
+}
+
+// the highest point reached by any valid launch, and how many valid launches there are
+fn solutions(target: &Target) -> Result<(i64, usize), String> {
+    let valid = velocities(target)?;
+    let best_y = valid
+        .iter()
+        .map(|(_, vy)| match *vy > 0 {
+            true => vy * (vy + 1) / 2,
+            false => 0,
+        })
+        .max()
+        .unwrap_or(i64::MIN);
+    Ok((best_y, valid.len()))
+}
+
+// how a launch ended, `Hit` gives the step that reached the target
+// an overshoot ends beyond the far side of the target, an undershoot stops short of it, heads away from it
+// or never rises to it,
+// and a probe that falls through drops past the target between two steps while level with it
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Outcome {
+    Hit(usize),
+    Overshoot,
+    Undershoot,
+    FellThrough,
+}
+
+// the probe positions from the launch at (0, 0) until it hits or can no longer hit
+#[derive(Debug, Clone, PartialEq)]
+struct Trajectory {
+    positions: Vec<(i64, i64)>,
+    outcome: Outcome,
+}
+
+impl Trajectory {
+    fn peak(&self) -> i64 {
+        self.positions.iter().map(|(_, y)| *y).max().unwrap_or(0)
+    }
+}
+
+fn trace(velocity: (i64, i64), target: &Target) -> Trajectory {
+    let (mut x, mut y) = (0, 0);
+    let (mut xv, mut yv) = velocity;
+    let mut positions = vec![(x, y)];
+
+    // drag never turns the probe around, so once past the far side, or across x = 0 from a target
+    // on one side of it (an edge at x = 0 counts), the probe can't come back
+    let beyond = |x: i64| match (target.xmin > 0, target.xmax < 0) {
+        (true, _) => x > target.xmax,
+        (_, true) => x < target.xmin,
+        _ => !target.contains_x(x),
+    };
+    let away = |x: i64| (target.xmin >= 0 && x < 0) || (target.xmax <= 0 && x > 0);
+
+    let outcome = loop {
+        x += xv;
+        y += yv;
+        xv -= xv.signum();
+        yv -= 1;
+        positions.push((x, y));
+
+        if target.contains_x(x) && target.contains_y(y) {
+            break Outcome::Hit(positions.len() - 1);
+        }
+        if away(x) {
+            break Outcome::Undershoot;
+        }
+        if beyond(x) {
+            break Outcome::Overshoot;
+        }
+        if y < target.ymin && yv < 0 {
+            let peak = positions.iter().map(|(_, y)| *y).max().unwrap_or(0);
+            break match target.contains_x(x) && peak >= target.ymin {
+                true => Outcome::FellThrough,
+                false => Outcome::Undershoot,
+            };
+        }
+    };
+    Trajectory { positions, outcome }
+}
+
+// the smallest box holding the trajectory and the target, top row first, with 'S' for the launcher,
+// '#' for the probe positions and 'T' for the target
+fn render(trajectory: &Trajectory, target: &Target) -> Vec<Vec<char>> {
+    let xs = trajectory
+        .positions
+        .iter()
+        .map(|(x, _)| *x)
+        .chain([target.xmin, target.xmax]);
+    let ys = trajectory
+        .positions
+        .iter()
+        .map(|(_, y)| *y)
+        .chain([target.ymin, target.ymax]);
+    let (xmin, xmax) = (xs.clone().min().unwrap(), xs.max().unwrap());
+    let (ymin, ymax) = (ys.clone().min().unwrap(), ys.max().unwrap());
+
+    let mut grid = vec![vec!['.'; (xmax - xmin + 1) as usize]; (ymax - ymin + 1) as usize];
+    let mut set = |x: i64, y: i64, glyph: char| grid[(ymax - y) as usize][(x - xmin) as usize] = glyph;
+    for y in target.ymin..=target.ymax {
+        for x in target.xmin..=target.xmax {
+            set(x, y, 'T');
+        }
+    }
+    for &(x, y) in &trajectory.positions[1..] {
+        set(x, y, '#');
+    }
+    set(0, 0, 'S');
+    grid
+}
+
+fn render_ascii(trajectory: &Trajectory, target: &Target) -> String {
+    let grid = render(trajectory, target);
+    let mut out = vec![];
+    write_text(&mut out, grid.len(), grid[0].len(), |r, c| grid[r][c]).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+// one pixel per position, the probe is green on a hit and red on a miss
+fn write_trajectory_ppm(file: &Path, trajectory: &Trajectory, target: &Target) -> std::io::Result<()> {
+    let grid = render(trajectory, target);
+    let probe = match trajectory.outcome {
+        Outcome::Hit(_) => [40, 220, 40],
+        _ => [230, 40, 40],
+    };
+    let mut out = BufWriter::new(File::create(file)?);
+    write_ppm(&mut out, grid.len(), grid[0].len(), |r, c| match grid[r][c] {
+        'S' => [255, 255, 255],
+        '#' => probe,
+        'T' => [40, 90, 200],
+        _ => [0, 0, 0],
+    })?;
+    out.flush()
+}
+
+fn parse_velocity(s: &str) -> Result<(i64, i64), String> {
+    let (vx, vy) = s.split_once(',').ok_or(format!("expected VX,VY, got {:?}", s))?;
+    let parse = |v: &str| v.trim().parse::<i64>().map_err(|e| format!("{:?}: {}", v, e));
+    Ok((parse(vx)?, parse(vy)?))
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -62,16 +284,57 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     struct Cli {
         #[structopt(short, long, parse(from_os_str), help = "file|stdin -- puzzle input")]
         input: Option<std::path::PathBuf>,
+
+        #[structopt(short, long, help = "list every valid initial velocity")]
+        velocities: bool,
+
+        #[structopt(short, long, parse(try_from_str = parse_velocity), help = "trace a launch at VX,VY")]
+        trace: Option<(i64, i64)>,
+
+        #[structopt(short, long, help = "draw the traced launch and the target")]
+        ascii: bool,
+
+        #[structopt(
+            long,
+            parse(from_os_str),
+            help = "write the traced launch and the target as a PPM image"
+        )]
+        ppm: Option<std::path::PathBuf>,
     }
     let args = Cli::from_args();
 
     // ==============================================================
 
     let data = read_data_lines::<String>(args.input)?;
-    let (best, n) = solutions(&data[0]);
+    let target = get_target_area(&data[0])?;
+    let (best, n) = solutions(&target)?;
     println!("Answer Part 1 = {:?}", best);
     println!("Answer Part 2 = {:?}", n);
 
+    if args.velocities {
+        let mut valid = velocities(&target)?.into_iter().collect::<Vec<_>>();
+        valid.sort_unstable();
+        for (vx, vy) in valid {
+            println!("{},{}", vx, vy);
+        }
+    }
+
+    if let Some(velocity) = args.trace {
+        let trajectory = trace(velocity, &target);
+        println!(
+            "{:?} after {} steps, peak y = {}",
+            trajectory.outcome,
+            trajectory.positions.len() - 1,
+            trajectory.peak()
+        );
+        if args.ascii {
+            print!("{}", render_ascii(&trajectory, &target));
+        }
+        if let Some(file) = args.ppm {
+            write_trajectory_ppm(&file, &trajectory, &target)?;
+        }
+    }
+
     Ok(())
 }
 
@@ -79,32 +342,129 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 mod tests {
     use super::*;
 
-    fn get_data(filename: &str) -> Vec<String> {
+    fn get_data(filename: &str) -> Target {
         let file = Some(std::path::PathBuf::from(filename));
-        read_data_lines::<String>(file).unwrap()
+        get_target_area(&read_data_lines::<String>(file).unwrap()[0]).unwrap()
+    }
+
+    // step by step simulation over a generous window of velocities
+    fn brute_force(target: &Target) -> HashSet<(i64, i64)> {
+        let mut valid = HashSet::new();
+        for vx in -60..=60 {
+            for vy in -60..=60 {
+                let (mut x, mut y, mut xv, mut yv) = (0, 0, vx, vy);
+                for _ in 0..500 {
+                    x += xv;
+                    y += yv;
+                    xv -= i64::signum(xv);
+                    yv -= 1;
+                    if target.contains_x(x) && target.contains_y(y) {
+                        valid.insert((vx, vy));
+                        break;
+                    }
+                }
+            }
+        }
+        valid
     }
 
     #[test]
     fn part1_example() {
-        let data = get_data("input-example");
-        assert_eq!(solutions(&data[0]).0, 45);
+        let target = get_data("input-example");
+        assert_eq!(solutions(&target).unwrap().0, 45);
     }
 
     #[test]
     fn part1_actual() {
-        let data = get_data("input-actual");
-        assert_eq!(solutions(&data[0]).0, 6786);
+        let target = get_data("input-actual");
+        assert_eq!(solutions(&target).unwrap().0, 6786);
     }
 
     #[test]
     fn part2_example() {
-        let data = get_data("input-example");
-        assert_eq!(solutions(&data[0]).1, 112);
+        let target = get_data("input-example");
+        assert_eq!(solutions(&target).unwrap().1, 112);
     }
 
     #[test]
     fn part2_actual() {
-        let data = get_data("input-actual");
-        assert_eq!(solutions(&data[0]).1, 2313);
+        let target = get_data("input-actual");
+        assert_eq!(solutions(&target).unwrap().1, 2313);
+    }
+
+    #[test]
+    fn any_quadrant() {
+        let example = velocities(&get_data("input-example")).unwrap();
+        assert!(example.contains(&(6, 9)) && example.contains(&(30, -10)));
+
+        let mirrored = get_target_area("target area: x=-30..-20, y=-10..-5").unwrap();
+        let valid = velocities(&mirrored).unwrap();
+        assert_eq!(valid, example.iter().map(|(vx, vy)| (-vx, *vy)).collect());
+
+        for area in [
+            "target area: x=5..12, y=3..8",
+            "target area: x=-12..-5, y=4..15",
+            "target area: x=22..27, y=-3..4",
+            "target area: x=20..30, y=-10..-5",
+        ] {
+            let target = get_target_area(area).unwrap();
+            assert_eq!(velocities(&target).unwrap(), brute_force(&target), "{}", area);
+        }
+
+        // the probe can stop at x = 0 inside a target spanning y = 0, for any upward launch
+        let target = get_target_area("target area: x=-4..6, y=-7..5").unwrap();
+        assert!(velocities(&target).is_err());
+        assert!(get_target_area("target area: x=1..2").is_err());
+    }
+
+    #[test]
+    fn tracing() {
+        let target = get_data("input-example");
+        let trajectory = trace((7, 2), &target);
+        assert_eq!(trajectory.outcome, Outcome::Hit(7));
+        assert_eq!(trajectory.positions[..4], [(0, 0), (7, 2), (13, 3), (18, 3)]);
+        assert_eq!(trajectory.peak(), 3);
+        assert_eq!(trace((6, 9), &target).peak(), 45);
+
+        assert_eq!(trace((17, -4), &target).outcome, Outcome::Overshoot);
+        assert_eq!(trace((3, 5), &target).outcome, Outcome::Undershoot);
+        assert_eq!(trace((6, 10), &target).outcome, Outcome::FellThrough);
+        assert_eq!(trace((-3, 5), &target).outcome, Outcome::Undershoot);
+        assert_eq!(trace((-1, 0), &target).positions, [(0, 0), (-1, 0)]);
+        assert_eq!(trace((-1, 0), &target).outcome, Outcome::Undershoot);
+        let mirrored = get_target_area("target area: x=-30..-20, y=-10..-5").unwrap();
+        assert_eq!(trace((-17, -4), &mirrored).outcome, Outcome::Overshoot);
+        assert_eq!(trace((2, 0), &mirrored).outcome, Outcome::Undershoot);
+        let spanning = get_target_area("target area: x=-5..5, y=-10..-5").unwrap();
+        assert_eq!(trace((-7, 0), &spanning).outcome, Outcome::Overshoot);
+        assert_eq!(trace((2, 3), &spanning).outcome, Outcome::Hit(9));
+        let edge = get_target_area("target area: x=0..5, y=-10..-5").unwrap();
+        assert_eq!(trace((-2, 0), &edge).outcome, Outcome::Undershoot);
+        assert_eq!(trace((7, 0), &edge).outcome, Outcome::Overshoot);
+        assert_eq!(trace((0, 0), &edge).outcome, Outcome::Hit(4));
+        let mirrored_edge = get_target_area("target area: x=-5..0, y=-10..-5").unwrap();
+        assert_eq!(trace((2, 0), &mirrored_edge).outcome, Outcome::Undershoot);
+        assert_eq!(trace((-7, 0), &mirrored_edge).outcome, Outcome::Overshoot);
+        let above = get_target_area("target area: x=5..12, y=30..40").unwrap();
+        assert_eq!(trace((4, 5), &above).outcome, Outcome::Undershoot);
+
+        // the example drawing from the puzzle
+        let ascii = render_ascii(&trace((7, 2), &target), &target);
+        let lines = ascii.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], ".............#....#............");
+        assert_eq!(lines[3], "S........................#.....");
+        assert_eq!(lines[8], "....................TTTTTTTTTTT");
+        assert_eq!(lines.len(), 14);
+
+        // every velocity in the solution set is traced as a hit and nothing else is
+        let valid = velocities(&target).unwrap();
+        for vx in -5..=35 {
+            for vy in -15..=15 {
+                let hit = matches!(trace((vx, vy), &target).outcome, Outcome::Hit(_));
+                assert_eq!(hit, valid.contains(&(vx, vy)), "{},{}", vx, vy);
+            }
+        }
+        assert_eq!(parse_velocity("6, -3"), Ok((6, -3)));
+        assert!(parse_velocity("6").is_err());
     }
 }
@@ -1,3 +1,4 @@
+use general::frames::{FrameFormat, FrameWriter};
 use general::read_data_lines;
 use ndarray::{Array, Array2, ArrayView};
 use structopt::StructOpt;
@@ -34,23 +35,29 @@ fn get_image(data: &[String]) -> Array2<Cell> {
     grid
 }
 
-#[allow(dead_code)]
-fn display(image: &Array2<Cell>) {
-    for row in image.rows() {
-        for elem in row {
-            match elem {
-                Cell::East => print!(">"),
-                Cell::South => print!("v"),
-                Cell::Empty => print!("."),
-            };
-        }
-        println!();
+// glyph and color of a cell for frame export
+fn cell_frame(cell: &Cell) -> (char, [u8; 3]) {
+    match cell {
+        Cell::East => ('>', [230, 90, 40]),
+        Cell::South => ('v', [40, 120, 230]),
+        Cell::Empty => ('.', [0, 0, 0]),
+    }
+}
+
+fn write_frame(image: &Array2<Cell>, frames: &mut Option<&mut FrameWriter>) -> std::io::Result<()> {
+    if let Some(writer) = frames {
+        writer.write(image.nrows(), image.ncols(), |r, c| cell_frame(&image[[r, c]]))?;
     }
-    println!();
+    Ok(())
 }
 
 fn solution1(image: &Array2<Cell>) -> usize {
-    //display(&image);
+    simulate(image, None).unwrap()
+}
+
+// run the herd until nothing moves, optionally writing a frame for the start and every step
+fn simulate(image: &Array2<Cell>, mut frames: Option<&mut FrameWriter>) -> std::io::Result<usize> {
+    write_frame(image, &mut frames)?;
     let nrows = image.nrows();
     let ncols = image.ncols();
     let mut new_image = image.clone();
@@ -61,7 +68,7 @@ fn solution1(image: &Array2<Cell>) -> usize {
         steps += 1;
 
         for mut row in new_image.rows_mut() {
-            // find all the ">." cells in the row and add to "swap" list 
+            // find all the ">." cells in the row and add to "swap" list
             let swaps = (0..row.len())
                 .filter(|&i| row[i] == Cell::East && row[(i + 1) % ncols] == Cell::Empty)
                 .collect::<Vec<_>>();
@@ -77,7 +84,7 @@ fn solution1(image: &Array2<Cell>) -> usize {
         }
 
         for mut col in new_image.columns_mut() {
-            // find all the "v." cells in the column and add to "swap" list 
+            // find all the "v." cells in the column and add to "swap" list
             let swaps = (0..col.len())
                 .filter(|&j| col[j] == Cell::South && col[(j + 1) % nrows] == Cell::Empty)
                 .collect::<Vec<_>>();
@@ -92,12 +99,14 @@ fn solution1(image: &Array2<Cell>) -> usize {
             }
         }
 
+        write_frame(&new_image, &mut frames)?;
+
         // all swap lists were empty
         if stuck {
             break;
         }
     }
-    steps
+    Ok(steps)
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -106,6 +115,12 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     struct Cli {
         #[structopt(short, long, parse(from_os_str), help = "file|stdin -- puzzle input")]
         input: Option<std::path::PathBuf>,
+
+        #[structopt(long, parse(from_os_str), help = "write a frame per step into this directory")]
+        frames: Option<std::path::PathBuf>,
+
+        #[structopt(long, help = "write frames as PPM images instead of text")]
+        ppm: bool,
     }
     let args = Cli::from_args();
 
@@ -115,6 +130,15 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let image = get_image(&data);
     println!("Answer Part 1 = {}", solution1(&image));
 
+    if let Some(dir) = args.frames {
+        let format = match args.ppm {
+            true => FrameFormat::Ppm,
+            false => FrameFormat::Text,
+        };
+        let mut writer = FrameWriter::new(dir, format)?;
+        simulate(&image, Some(&mut writer))?;
+    }
+
     Ok(())
 }
 
@@ -138,4 +162,17 @@ mod tests {
         let image = get_test_data("input-actual");
         assert_eq!(360, solution1(&image));
     }
+
+    #[test]
+    fn frames_example() {
+        let image = get_test_data("input-example");
+        let dir = std::env::temp_dir().join(format!("day_25_frames_{}", std::process::id()));
+        let mut writer = FrameWriter::new(&dir, FrameFormat::Text).unwrap();
+        assert_eq!(58, simulate(&image, Some(&mut writer)).unwrap());
+        assert_eq!(writer.frames(), 59);
+
+        let last = std::fs::read_to_string(dir.join("frame_000058.txt")).unwrap();
+        assert!(last.starts_with("..>>v>vv..\n"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
 }
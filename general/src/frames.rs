@@ -0,0 +1,88 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+// Writes simulation steps into a directory as numbered frames,
+// either plain text (one char per cell) or binary PPM images (one pixel per cell)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FrameFormat {
+    Text,
+    Ppm,
+}
+
+#[derive(Debug)]
+pub struct FrameWriter {
+    dir: PathBuf,
+    format: FrameFormat,
+    frame: usize,
+}
+
+impl FrameWriter {
+    // creates the directory if needed, frames are numbered from 0
+    pub fn new<P>(dir: P, format: FrameFormat) -> io::Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        fs::create_dir_all(&dir)?;
+        Ok(Self {
+            dir: dir.as_ref().to_path_buf(),
+            format,
+            frame: 0,
+        })
+    }
+
+    // number of frames written so far
+    pub fn frames(&self) -> usize {
+        self.frame
+    }
+
+    // writes the next frame of a nrows x ncols grid, `cell` supplies the glyph and RGB color of (row, col)
+    pub fn write<F>(&mut self, nrows: usize, ncols: usize, cell: F) -> io::Result<PathBuf>
+    where
+        F: Fn(usize, usize) -> (char, [u8; 3]),
+    {
+        let extension = match self.format {
+            FrameFormat::Text => "txt",
+            FrameFormat::Ppm => "ppm",
+        };
+        let path = self.dir.join(format!("frame_{:06}.{}", self.frame, extension));
+        let mut out = BufWriter::new(File::create(&path)?);
+
+        match self.format {
+            FrameFormat::Text => write_text(&mut out, nrows, ncols, |r, c| cell(r, c).0)?,
+            FrameFormat::Ppm => write_ppm(&mut out, nrows, ncols, |r, c| cell(r, c).1)?,
+        }
+        out.flush()?;
+
+        self.frame += 1;
+        Ok(path)
+    }
+}
+
+// writes a nrows x ncols grid as lines of text, `glyph` supplies the char of (row, col)
+pub fn write_text<W, F>(out: &mut W, nrows: usize, ncols: usize, glyph: F) -> io::Result<()>
+where
+    W: Write,
+    F: Fn(usize, usize) -> char,
+{
+    for r in 0..nrows {
+        let row = (0..ncols).map(|c| glyph(r, c)).collect::<String>();
+        writeln!(out, "{}", row)?;
+    }
+    Ok(())
+}
+
+// writes a nrows x ncols grid as a binary PPM image, `color` supplies the RGB pixel of (row, col)
+pub fn write_ppm<W, F>(out: &mut W, nrows: usize, ncols: usize, color: F) -> io::Result<()>
+where
+    W: Write,
+    F: Fn(usize, usize) -> [u8; 3],
+{
+    write!(out, "P6\n{} {}\n255\n", ncols, nrows)?;
+    for r in 0..nrows {
+        for c in 0..ncols {
+            out.write_all(&color(r, c))?;
+        }
+    }
+    Ok(())
+}
@@ -1,3 +1,6 @@
+pub mod frames;
+pub mod ocr;
+
 use std::fs::File;
 use std::io::{self, BufRead};
 use std::path::{Path, PathBuf};
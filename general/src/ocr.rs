@@ -0,0 +1,135 @@
+// Recognizes the 6 row letter font drawn by Advent of Code puzzles, letters are 4 columns wide except Y
+// which takes 5, '#' is an on pixel, anything else is off, letters are separated by a blank column
+// except after Y, which fills its whole 5 column cell so the next letter may start right after it
+//
+// Example:
+//   .##..###..
+//   #..#.#..#.
+//   #..#.###..
+//   ####.#..#.
+//   #..#.#..#.
+//   #..#.###..
+// Returns:
+//   ("AB", [])
+
+const GLYPH_WIDTH: usize = 4; // width assumed for unrecognized glyphs
+const GLYPH_HEIGHT: usize = 6;
+
+const FONT: [(char, [&str; GLYPH_HEIGHT]); 18] = [
+    ('A', [".##.", "#..#", "#..#", "####", "#..#", "#..#"]),
+    ('B', ["###.", "#..#", "###.", "#..#", "#..#", "###."]),
+    ('C', [".##.", "#..#", "#...", "#...", "#..#", ".##."]),
+    ('E', ["####", "#...", "###.", "#...", "#...", "####"]),
+    ('F', ["####", "#...", "###.", "#...", "#...", "#..."]),
+    ('G', [".##.", "#..#", "#...", "#.##", "#..#", ".###"]),
+    ('H', ["#..#", "#..#", "####", "#..#", "#..#", "#..#"]),
+    ('I', [".###", "..#.", "..#.", "..#.", "..#.", ".###"]),
+    ('J', ["..##", "...#", "...#", "...#", "#..#", ".##."]),
+    ('K', ["#..#", "#.#.", "##..", "#.#.", "#.#.", "#..#"]),
+    ('L', ["#...", "#...", "#...", "#...", "#...", "####"]),
+    ('O', [".##.", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('P', ["###.", "#..#", "#..#", "###.", "#...", "#..."]),
+    ('R', ["###.", "#..#", "#..#", "###.", "#.#.", "#..#"]),
+    ('S', [".###", "#...", "#...", ".##.", "...#", "###."]),
+    ('U', ["#..#", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('Y', ["#...#", "#...#", ".#.#.", "..#..", "..#..", "..#.."]),
+    ('Z', ["####", "...#", "..#.", ".#..", "#...", "####"]),
+];
+
+// Returns the decoded text, with '?' for each glyph that isn't in the font,
+// and the (position, art) of those unrecognized glyphs
+pub fn read_letters(art: &str) -> (String, Vec<(usize, String)>) {
+    // normalize to '#'/'.' rows and drop blank rows above and below the letters
+    let mut rows = art
+        .lines()
+        .map(|line| {
+            line.chars()
+                .map(|c| if c == '#' { '#' } else { '.' })
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    let blank = |row: &Vec<char>| row.iter().all(|&c| c == '.');
+    while rows.last().is_some_and(blank) {
+        rows.pop();
+    }
+    while rows.first().is_some_and(blank) {
+        rows.remove(0);
+    }
+
+    let width = rows.iter().map(|row| row.len()).max().unwrap_or(0);
+    let pixel = |r: usize, c: usize| *rows[r].get(c).unwrap_or(&'.');
+
+    let mut text = String::new();
+    let mut unknown = vec![];
+    let mut left = 0;
+    for i in 0.. {
+        // ignore trailing blank cells
+        if (left..width).all(|c| (0..rows.len()).all(|r| pixel(r, c) == '.')) {
+            break;
+        }
+
+        let glyph = |w: usize| {
+            (0..rows.len())
+                .map(|r| (left..left + w).map(|c| pixel(r, c)).collect::<String>())
+                .collect::<Vec<_>>()
+        };
+        let spaced = |w: usize| (0..rows.len()).all(|r| pixel(r, left + w) == '.');
+
+        // a glyph as wide as the whole cell needs no blank column after it
+        let found = FONT.iter().find(|(_, g)| {
+            let w = g[0].len();
+            (w > GLYPH_WIDTH || spaced(w)) && g[..] == glyph(w)[..]
+        });
+        match found {
+            Some((letter, g)) => {
+                let w = g[0].len();
+                text.push(*letter);
+                left += w + spaced(w) as usize;
+            }
+            None => {
+                text.push('?');
+                unknown.push((i, glyph(GLYPH_WIDTH).join("\n")));
+                left += GLYPH_WIDTH + 1;
+            }
+        }
+    }
+    (text, unknown)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn five_wide_y() {
+        let art = [
+            "#...#.###..#..#.",
+            "#...#.#..#.#..#.",
+            ".#.#..###..#..#.",
+            "..#...#..#.#..#.",
+            "..#...#..#.#..#.",
+            "..#...###...##..",
+        ]
+        .join("\n");
+        assert_eq!(read_letters(&art), ("YBU".to_string(), vec![]));
+
+        // an unknown glyph doesn't shift the letters after it
+        let art = art.replace("#...#.###", "#...#.##.");
+        assert_eq!(read_letters(&art).0, "Y?U");
+    }
+
+    #[test]
+    fn five_wide_y_at_cell_pitch() {
+        // letters on a 5 column pitch, the Y fills its cell and the B starts right after it
+        let art = [
+            ".##..#...####..#..#.",
+            "#..#.#...##..#.#..#.",
+            "#..#..#.#.###..#..#.",
+            "####...#..#..#.#..#.",
+            "#..#...#..#..#.#..#.",
+            "#..#...#..###...##..",
+        ]
+        .join("\n");
+        assert_eq!(read_letters(&art), ("AYBU".to_string(), vec![]));
+    }
+}